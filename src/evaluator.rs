@@ -3,25 +3,230 @@ use types::Error::*;
 use types::Expr::*;
 use types::EnvValue::*;
 use types::Result;
+use number::{Number, MAX_DIGITS};
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use std::f64;
 use std::result::Result as StdResult;
 
+fn from_bool(b: bool) -> Number {
+    if b { Number::one() } else { Number::zero() }
+}
+
+fn integral_operands(
+    env: &mut Environment,
+    op: &str,
+    expr1: Expr,
+    expr2: Expr,
+) -> StdResult<(BigInt, BigInt), Error> {
+    let lhs = evaluate(env, expr1)?.to_integer();
+    let rhs = evaluate(env, expr2)?.to_integer();
+    match (lhs, rhs) {
+        (Some(lhs), Some(rhs)) => Ok((lhs, rhs)),
+        _ => Err(NonIntegralOperand(op.to_string())),
+    }
+}
+
+/// Adds `delta` to an existing variable, returning its old and new values.
+fn increment(
+    env: &mut Environment,
+    var_name: String,
+    delta: i64,
+) -> StdResult<(Number, Number), Error> {
+    let old = evaluate(env, EVar(var_name.clone()))?;
+    let new = old.add(&Number::from(delta));
+    store(env, var_name, new.clone(), false)?;
+    Ok((old, new))
+}
+
+/// Stores `value` in a variable, declaring it in the innermost scope if
+/// `declare` is set, or changes the setting a special variable stands for.
+fn store(
+    env: &mut Environment,
+    var_name: String,
+    value: Number,
+    declare: bool,
+) -> StdResult<(), Error> {
+    if let Some(result) = env.set_special(&var_name, &value) {
+        return result;
+    }
+    if declare {
+        env.add(var_name, ComputedResult(value));
+    } else {
+        env.assign(var_name, ComputedResult(value));
+    }
+    Ok(())
+}
+
+fn shift_amount(amount: BigInt) -> StdResult<usize, Error> {
+    amount.to_usize().ok_or(InvalidShift)
+}
+
+/// Whether a result whose magnitude is estimated at `10^log10` would be too
+/// large to compute. See `Number::log10_abs`.
+pub fn overflows(log10: f64) -> bool {
+    log10 > f64::from(MAX_DIGITS)
+}
+
+/// Whether the value of `statement` goes unprinted. When bc is emulated,
+/// assignments and definitions print nothing, as in bc.
+pub fn is_quiet(env: &Environment, statement: &Expr) -> bool {
+    match *statement {
+        ELocated(_, ref statement) => is_quiet(env, statement),
+        EAssign(..) | ELet(..) | EDefun(..) => env.bc_mathlib(),
+        _ => false,
+    }
+}
+
+/// Reports `error`, or in IEEE mode, returns the infinity or NaN that floating
+/// point arithmetic would have produced instead.
+fn arithmetic_error(env: &Environment, error: Error, ieee_value: Number) -> Result {
+    if env.ieee() {
+        Ok(ieee_value)
+    } else {
+        Err(error)
+    }
+}
+
+// An overflow of `op`, or an infinity with the sign of the result in IEEE mode
+fn overflow(env: &Environment, op: &str, negative: bool) -> Result {
+    arithmetic_error(env, Overflow(op.to_string()), Number::infinity(negative))
+}
+
+// The IEEE result of dividing `dividend` by zero
+fn divided_by_zero(dividend: &Number) -> Number {
+    if dividend.is_zero() {
+        Number::nan()
+    } else {
+        Number::infinity(dividend.is_negative())
+    }
+}
+
 pub fn evaluate(env: &mut Environment, expr: Expr) -> Result {
     match expr {
         ENum(num) => Ok(num),
-        EAdd(expr1, expr2) => Ok(evaluate(env, *expr1)? + evaluate(env, *expr2)?),
-        ESub(expr1, expr2) => Ok(evaluate(env, *expr1)? - evaluate(env, *expr2)?),
-        EMul(expr1, expr2) => Ok(evaluate(env, *expr1)? * evaluate(env, *expr2)?),
-        EDiv(expr1, expr2) => Ok(evaluate(env, *expr1)? / evaluate(env, *expr2)?),
-        EExp(expr1, expr2) => Ok(evaluate(env, *expr1)?.powf(evaluate(env, *expr2)?)),
+        ENeg(expr) => Ok(evaluate(env, *expr)?.neg()),
+        EAdd(expr1, expr2) => Ok(evaluate(env, *expr1)?.add(&evaluate(env, *expr2)?)),
+        ESub(expr1, expr2) => Ok(evaluate(env, *expr1)?.sub(&evaluate(env, *expr2)?)),
+        EMul(expr1, expr2) => {
+            let scale = env.scale();
+            let (lhs, rhs) = (evaluate(env, *expr1)?, evaluate(env, *expr2)?);
+            if overflows(lhs.log10_abs() + rhs.log10_abs()) {
+                let negative = lhs.is_negative() != rhs.is_negative();
+                return overflow(env, "*", negative);
+            }
+            Ok(lhs.mul(&rhs, scale))
+        }
+        EDiv(expr1, expr2) => {
+            let scale = env.scale();
+            let (lhs, rhs) = (evaluate(env, *expr1)?, evaluate(env, *expr2)?);
+            // Division by zero is reported below, rather than as an overflow
+            if !rhs.is_zero() && overflows(lhs.log10_abs() - rhs.log10_abs()) {
+                let negative = lhs.is_negative() != rhs.is_negative();
+                return overflow(env, "/", negative);
+            }
+            match lhs.div(&rhs, scale) {
+                Some(result) => Ok(result),
+                None => arithmetic_error(env, DivisionByZero, divided_by_zero(&lhs)),
+            }
+        }
+        EExp(expr1, expr2) => {
+            let scale = env.scale();
+            let (base, exp) = (evaluate(env, *expr1)?, evaluate(env, *expr2)?);
+            let exp_int = exp.to_f64().trunc();
+            if overflows(base.log10_abs() * exp_int) {
+                let negative = base.is_negative() && exp_int % 2.0 != 0.0;
+                return overflow(env, "^", negative);
+            }
+            match base.pow(&exp, scale) {
+                Some(result) => Ok(result),
+                None => arithmetic_error(env, DivisionByZero, Number::infinity(false)),
+            }
+        }
+        EIntDiv(expr1, expr2) => {
+            let (lhs, rhs) = (evaluate(env, *expr1)?, evaluate(env, *expr2)?);
+            // Division by zero is reported below, rather than as an overflow
+            if !rhs.is_zero() && overflows(lhs.log10_abs() - rhs.log10_abs()) {
+                let negative = lhs.is_negative() != rhs.is_negative();
+                return overflow(env, "//", negative);
+            }
+            match lhs.int_div(&rhs) {
+                Some(result) => Ok(result),
+                None => arithmetic_error(env, DivisionByZero, divided_by_zero(&lhs)),
+            }
+        }
+        EMod(expr1, expr2) => {
+            let (lhs, rhs) = (evaluate(env, *expr1)?, evaluate(env, *expr2)?);
+            match lhs.rem(&rhs) {
+                Some(result) => Ok(result),
+                None => arithmetic_error(env, DivisionByZero, Number::nan()),
+            }
+        }
+        EBitAnd(expr1, expr2) => {
+            let (lhs, rhs) = integral_operands(env, "&", *expr1, *expr2)?;
+            Ok(Number::from(lhs & rhs))
+        }
+        EBitOr(expr1, expr2) => {
+            let (lhs, rhs) = integral_operands(env, "|", *expr1, *expr2)?;
+            Ok(Number::from(lhs | rhs))
+        }
+        EBitXor(expr1, expr2) => {
+            let (lhs, rhs) = integral_operands(env, "xor", *expr1, *expr2)?;
+            Ok(Number::from(lhs ^ rhs))
+        }
+        EShl(expr1, expr2) => {
+            let (lhs, rhs) = integral_operands(env, "<<", *expr1, *expr2)?;
+            let amount = shift_amount(rhs)?;
+            let lhs = Number::from(lhs);
+            if overflows(lhs.log10_abs() + amount as f64 * f64::consts::LOG10_2) {
+                let negative = lhs.is_negative();
+                return overflow(env, "<<", negative);
+            }
+            Ok(Number::from(lhs.trunc() << amount))
+        }
+        EShr(expr1, expr2) => {
+            let (lhs, rhs) = integral_operands(env, ">>", *expr1, *expr2)?;
+            Ok(Number::from(lhs >> shift_amount(rhs)?))
+        }
+        EEq(expr1, expr2) => Ok(from_bool(evaluate(env, *expr1)? == evaluate(env, *expr2)?)),
+        ENe(expr1, expr2) => Ok(from_bool(evaluate(env, *expr1)? != evaluate(env, *expr2)?)),
+        ELt(expr1, expr2) => Ok(from_bool(evaluate(env, *expr1)? < evaluate(env, *expr2)?)),
+        ELe(expr1, expr2) => Ok(from_bool(evaluate(env, *expr1)? <= evaluate(env, *expr2)?)),
+        EGt(expr1, expr2) => Ok(from_bool(evaluate(env, *expr1)? > evaluate(env, *expr2)?)),
+        EGe(expr1, expr2) => Ok(from_bool(evaluate(env, *expr1)? >= evaluate(env, *expr2)?)),
+        EAnd(expr1, expr2) => {
+            // short-circuits, as in bc
+            let result = !evaluate(env, *expr1)?.is_zero() && !evaluate(env, *expr2)?.is_zero();
+            Ok(from_bool(result))
+        }
+        EOr(expr1, expr2) => {
+            let result = !evaluate(env, *expr1)?.is_zero() || !evaluate(env, *expr2)?.is_zero();
+            Ok(from_bool(result))
+        }
+        ENot(expr) => Ok(from_bool(evaluate(env, *expr)?.is_zero())),
         ELet(varname, expr) => {
-            let result = evaluate(env, *expr.clone())?;
-            env.add(varname, ComputedResult(result));
+            let result = evaluate(env, *expr)?;
+            store(env, varname, result.clone(), true)?;
             Ok(result)
         }
+        EAssign(varname, expr) => {
+            let result = evaluate(env, *expr)?;
+            store(env, varname, result.clone(), false)?;
+            Ok(result)
+        }
+        EPreInc(varname) => Ok(increment(env, varname, 1)?.1),
+        EPreDec(varname) => Ok(increment(env, varname, -1)?.1),
+        EPostInc(varname) => Ok(increment(env, varname, 1)?.0),
+        EPostDec(varname) => Ok(increment(env, varname, -1)?.0),
         EVar(varname) => {
+            if let Some(value) = env.special(&varname) {
+                return Ok(value);
+            }
             if let Some(result) = env.get(varname.clone()) {
                 match result {
                     ComputedResult(v) => Ok(v),
+                    // Native functions without arguments are constants, like `pi`
+                    NativeFn(ref f) if f.arity == Arity::Exactly(0) => f.call(&[], env.scale()),
                     _ => Err(InvalidVariableReference(varname.clone())),
                 }
             } else {
@@ -30,76 +235,218 @@ pub fn evaluate(env: &mut Environment, expr: Expr) -> Result {
         }
         EDefun(fun_name, Lambda { params, body }) => {
             env.add(fun_name.clone(), LambdaRef(Lambda { params, body }));
-            Ok(0.0)
-        }
-        EFunCall(func_name, args) => {
-            if let Some(defun) = env.get(func_name.clone()) {
-                match defun {
-                    LambdaRef(Lambda { params, body }) => {
-                        if args.len() != params.len() {
-                            Err(InvalidLambdaArgs(
-                                func_name.clone(),
-                                params.len(),
-                                args.len(),
-                            ))
-                        } else {
-                            let mut cloned_environment = env.clone();
-                            let maybe_args: StdResult<Vec<f32>, _> =
-                                args.into_iter().map(|arg| evaluate(env, arg)).collect();
-                            params.into_iter().zip(maybe_args?.into_iter()).fold(
-                                &mut cloned_environment,
-                                |env, value| env.add(value.0, ComputedResult(value.1)),
-                            );
-                            body.into_iter().fold(Ok(0.0), |_, expr| {
-                                evaluate(&mut cloned_environment, expr)
-                            })
-                        }
-                    }
-                    NativeFn(f) => {
-                        if args.len() > 1 {
-                            Err(InvalidNativeFunctionArgs(func_name.clone(), args.len()))
-                        } else {
-                            let result = evaluate(env, args.into_iter().nth(0).unwrap())?;
-                            Ok(f(result))
-                        }
-                    }
-                    _ => Err(InvalidFunctionReference(func_name.clone())),
-                }
-            } else {
-                Err(UndefinedFunction(func_name.clone()))
-            }
+            Ok(Number::zero())
         }
+        EFunCall(func_name, args) => call_function(env, func_name, args, None),
         EReturn(expr) => evaluate(env, *expr),
-        EIf(ifexprs, elseexpr) => {
-            let bools: StdResult<Vec<bool>, _> = ifexprs
-                .iter()
-                .map(|ifexpr| {
-                    let (lhs, rhs) = ifexpr.clone().condition;
-                    Ok(evaluate(env, lhs)? == evaluate(env, rhs)?)
-                })
-                .collect();
-            ifexprs
-                .into_iter()
-                .zip(bools?.into_iter())
-                .find(|p| p.1 == true)
-                .map(|(ex, _)| {
-                    let mut cloned_environment = env.clone();
-                    let result = ex.body.into_iter().fold(Ok(0.0), |_, expr| {
-                        evaluate(&mut cloned_environment, expr)
-                    });
-                    result
-                })
-                .unwrap_or_else(|| {
-                    let mut cloned_environment = env.clone();
-                    elseexpr.into_iter().fold(
-                        Ok(0.0),
-                        |_env, expr| evaluate(&mut cloned_environment, expr),
-                    )
-                })
+        EIf(ifexprs, elseexpr) => finish(execute_if(env, ifexprs, elseexpr)?),
+        EWhile(condition, body) => finish(execute_while(env, *condition, body)?),
+        EFor(init, condition, step, body) => {
+            finish(execute_for(env, init, condition, step, body)?)
+        }
+        EBreak => finish(Break(None)),
+        EContinue => finish(Continue(None)),
+        ELocated(span, expr) => {
+            let result = match *expr {
+                EFunCall(func_name, args) => call_function(env, func_name, args, Some(span)),
+                expr => evaluate(env, expr),
+            };
+            result.map_err(|error| error.at(span))
         }
     }
 }
 
+/// Calls a function defined in the environment. Errors raised by the body of
+/// a user-defined function are wrapped with the name of the function and the
+/// position of the call, if known, so that they read like a backtrace.
+fn call_function(
+    env: &mut Environment,
+    func_name: String,
+    args: Vec<Expr>,
+    call_site: Option<Span>,
+) -> Result {
+    match env.get(func_name.clone()) {
+        Some(LambdaRef(Lambda { params, body })) => {
+            check_arity(&func_name, Arity::Exactly(params.len()), args.len())?;
+            let maybe_args: StdResult<Vec<Number>, _> =
+                args.into_iter().map(|arg| evaluate(env, arg)).collect();
+            let maybe_args = maybe_args?;
+            let caller_scopes = env.enter_function();
+            params.into_iter().zip(maybe_args).fold(
+                &mut *env,
+                |env, value| env.add(value.0, ComputedResult(value.1)),
+            );
+            let result = execute_block(env, body).and_then(finish);
+            env.leave_function(caller_scopes);
+            result.map_err(|error| match call_site {
+                Some(span) => InFunction(func_name, span, Box::new(error)),
+                None => error,
+            })
+        }
+        Some(NativeFn(f)) => {
+            check_arity(&func_name, f.arity, args.len())?;
+            let args: StdResult<Vec<Number>, _> =
+                args.into_iter().map(|arg| evaluate(env, arg)).collect();
+            match f.call(&args?, env.scale()) {
+                Err(WithIeeeValue(_, value)) if env.ieee() => Ok(value),
+                Err(WithIeeeValue(error, _)) => Err(*error),
+                Err(DomainError(..)) if env.ieee() => Ok(Number::nan()),
+                Err(Overflow(..)) if env.ieee() => Ok(Number::infinity(false)),
+                result => result,
+            }
+        }
+        Some(_) => Err(InvalidFunctionReference(func_name)),
+        None => Err(UndefinedFunction(func_name)),
+    }
+}
+
+fn check_arity(func_name: &str, arity: Arity, count: usize) -> StdResult<(), Error> {
+    if arity.accepts(count) {
+        Ok(())
+    } else {
+        Err(InvalidArgumentCount(func_name.to_string(), arity, count))
+    }
+}
+
+/// The outcome of executing a statement: either the statement's value, or a
+/// `return`, `break` or `continue` that has to unwind the enclosing blocks up
+/// to the function call or loop that handles it. A `break` or `continue`
+/// carries the position of its statement, in case there is no loop to handle
+/// it.
+enum Flow {
+    Next(Number),
+    Return(Number),
+    Break(Option<Span>),
+    Continue(Option<Span>),
+}
+
+use self::Flow::*;
+
+impl Flow {
+    fn at(self, span: Span) -> Flow {
+        match self {
+            Break(None) => Break(Some(span)),
+            Continue(None) => Continue(Some(span)),
+            flow => flow,
+        }
+    }
+}
+
+/// The value of a statement executed outside of any loop, where there is
+/// nothing for `break` or `continue` to stop.
+fn finish(flow: Flow) -> Result {
+    let (keyword, span) = match flow {
+        Next(value) | Return(value) => return Ok(value),
+        Break(span) => ("break", span),
+        Continue(span) => ("continue", span),
+    };
+    let error = LoopControlOutsideLoop(keyword.to_string());
+    Err(match span {
+        Some(span) => error.at(span),
+        None => error,
+    })
+}
+
+fn execute(env: &mut Environment, expr: Expr) -> StdResult<Flow, Error> {
+    match expr {
+        EReturn(expr) => Ok(Return(evaluate(env, *expr)?)),
+        EIf(ifexprs, elseexpr) => execute_if(env, ifexprs, elseexpr),
+        EWhile(condition, body) => execute_while(env, *condition, body),
+        EFor(init, condition, step, body) => execute_for(env, init, condition, step, body),
+        EBreak => Ok(Break(None)),
+        EContinue => Ok(Continue(None)),
+        ELocated(span, expr) => match *expr {
+            // Calls need their position to show up in backtraces
+            call @ EFunCall(..) => Ok(Next(evaluate(env, ELocated(span, Box::new(call)))?)),
+            expr => execute(env, expr).map(|flow| flow.at(span)).map_err(|error| error.at(span)),
+        },
+        expr => Ok(Next(evaluate(env, expr)?)),
+    }
+}
+
+/// Executes the statements of a block in order, stopping at the first
+/// `return`, `break` or `continue`. Otherwise the block's value is that of
+/// its last statement.
+fn execute_block(env: &mut Environment, body: Vec<Expr>) -> StdResult<Flow, Error> {
+    let mut result = Next(Number::zero());
+    for expr in body {
+        result = execute(env, expr)?;
+        if let Next(_) = result {
+            continue;
+        }
+        break;
+    }
+    Ok(result)
+}
+
+fn is_true(env: &mut Environment, condition: &Expr) -> StdResult<bool, Error> {
+    Ok(!evaluate(env, condition.clone())?.is_zero())
+}
+
+// Loops don't introduce a scope of their own: the body runs directly in the
+// surrounding environment, so that it can update the loop variables.
+fn execute_while(
+    env: &mut Environment,
+    condition: Expr,
+    body: Vec<Expr>,
+) -> StdResult<Flow, Error> {
+    while is_true(env, &condition)? {
+        match execute_block(env, body.clone())? {
+            Return(value) => return Ok(Return(value)),
+            Break(_) => break,
+            Next(_) | Continue(_) => (),
+        }
+    }
+    Ok(Next(Number::zero()))
+}
+
+fn execute_for(
+    env: &mut Environment,
+    init: Option<Box<Expr>>,
+    condition: Option<Box<Expr>>,
+    step: Option<Box<Expr>>,
+    body: Vec<Expr>,
+) -> StdResult<Flow, Error> {
+    if let Some(init) = init {
+        evaluate(env, *init)?;
+    }
+    loop {
+        if let Some(ref condition) = condition {
+            if !is_true(env, condition)? {
+                break;
+            }
+        }
+        match execute_block(env, body.clone())? {
+            Return(value) => return Ok(Return(value)),
+            Break(_) => break,
+            Next(_) | Continue(_) => (),
+        }
+        if let Some(ref step) = step {
+            evaluate(env, (**step).clone())?;
+        }
+    }
+    Ok(Next(Number::zero()))
+}
+
+fn execute_if(
+    env: &mut Environment,
+    ifexprs: Vec<IfExpr>,
+    elseexpr: Vec<Expr>,
+) -> StdResult<Flow, Error> {
+    let mut taken = None;
+    for IfExpr { condition, body } in ifexprs {
+        if is_true(env, &condition)? {
+            taken = Some(body);
+            break;
+        }
+    }
+    env.push_scope();
+    let body = taken.unwrap_or(elseexpr);
+    let result = execute_block(env, body);
+    env.pop_scope();
+    result
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -107,48 +454,176 @@ mod tests {
 
     #[test]
     fn test_evaluate_add_expression() {
-        let expr = EAdd(Box::new(ENum(1.0)), Box::new(ENum(2.0)));
-        assert_eq!(evaluate(&mut Environment::new(), expr).unwrap(), 3.0);
+        let expr = EAdd(Box::new(ENum(Number::from(1))), Box::new(ENum(Number::from(2))));
+        assert_eq!(evaluate(&mut Environment::new(), expr).unwrap(), Number::from(3));
     }
 
     #[test]
     fn test_evaluate_subtraction_expression() {
-        let expr = ESub(Box::new(ENum(3.0)), Box::new(ENum(2.0)));
-        assert_eq!(evaluate(&mut Environment::new(), expr).unwrap(), 1.0);
+        let expr = ESub(Box::new(ENum(Number::from(3))), Box::new(ENum(Number::from(2))));
+        assert_eq!(evaluate(&mut Environment::new(), expr).unwrap(), Number::from(1));
     }
 
     #[test]
     fn test_evaluate_multiplication_expression() {
-        let expr = EMul(Box::new(ENum(3.0)), Box::new(ENum(2.0)));
-        assert_eq!(evaluate(&mut Environment::new(), expr).unwrap(), 6.0);
+        let expr = EMul(Box::new(ENum(Number::from(3))), Box::new(ENum(Number::from(2))));
+        assert_eq!(evaluate(&mut Environment::new(), expr).unwrap(), Number::from(6));
     }
 
     #[test]
     fn test_evaluate_division_expression() {
-        let expr = EDiv(Box::new(ENum(3.0)), Box::new(ENum(2.0)));
-        assert_eq!(evaluate(&mut Environment::new(), expr).unwrap(), 1.5);
+        let expr = EDiv(Box::new(ENum(Number::from(3))), Box::new(ENum(Number::from(2))));
+        assert_eq!(evaluate(&mut Environment::new(), expr).unwrap(), "1.5".parse().unwrap());
     }
 
     #[test]
     fn test_evaluate_nested_arithmetic_expression() {
         let expr = EAdd(
-            Box::new(EMul(Box::new(ENum(1.0)), Box::new(ENum(2.0)))),
+            Box::new(EMul(Box::new(ENum(Number::from(1))), Box::new(ENum(Number::from(2))))),
             Box::new(EDiv(
-                Box::new(EExp(Box::new(ENum(6.0)), Box::new(ENum(2.0)))),
-                Box::new(ENum(5.0)),
+                Box::new(EExp(Box::new(ENum(Number::from(6))), Box::new(ENum(Number::from(2))))),
+                Box::new(ENum(Number::from(5))),
             )),
         );
-        assert_eq!(evaluate(&mut Environment::new(), expr).unwrap(), 9.2);
+        assert_eq!(evaluate(&mut Environment::new(), expr).unwrap(), "9.2".parse().unwrap());
+    }
+
+    #[test]
+    fn test_evaluate_negation_expression() {
+        let expr = EAdd(
+            Box::new(ENeg(Box::new(ENum(Number::from(5))))),
+            Box::new(ENum(Number::from(2))),
+        );
+        assert_eq!(evaluate(&mut Environment::new(), expr).unwrap(), Number::from(-3));
+    }
+
+    #[test]
+    fn test_evaluate_modulo_and_integer_division() {
+        let mut env = Environment::new();
+        let num = |s: &str| Box::new(ENum(s.parse().unwrap()));
+        assert_eq!(evaluate(&mut env, EMod(num("17"), num("5"))).unwrap(), Number::from(2));
+        assert_eq!(
+            evaluate(&mut env, EMod(num("5.5"), num("2"))).unwrap(),
+            "1.5".parse().unwrap()
+        );
+        assert_eq!(evaluate(&mut env, EIntDiv(num("17"), num("5"))).unwrap(), Number::from(3));
+        assert!(evaluate(&mut env, EMod(num("1"), num("0"))).is_err());
+    }
+
+    #[test]
+    fn test_evaluate_arithmetic_errors() {
+        let mut env = Environment::new();
+        let num = |s: &str| Box::new(ENum(s.parse().unwrap()));
+        match evaluate(&mut env, EDiv(num("1"), num("0"))) {
+            Err(DivisionByZero) => (),
+            result => panic!("expected division by zero, got {:?}", result),
+        }
+        match evaluate(&mut env, EExp(num("10"), num("2000000"))) {
+            Err(Overflow(op)) => assert_eq!(op, "^"),
+            result => panic!("expected an overflow, got {:?}", result),
+        }
+        match evaluate(&mut env, EShl(num("1"), num("10000000"))) {
+            Err(Overflow(op)) => assert_eq!(op, "<<"),
+            result => panic!("expected an overflow, got {:?}", result),
+        }
+        let sqrt = EFunCall(String::from("sqrt"), vec![ENeg(num("4"))]);
+        match evaluate(&mut env, sqrt) {
+            Err(error @ DomainError(..)) => {
+                assert_eq!(error.to_string(), "Runtime Error: -4 is outside the domain of sqrt")
+            }
+            result => panic!("expected a domain error, got {:?}", result),
+        }
+        // Exponents beyond the range of an f64 have an infinite estimate
+        let exps = [ENum("1e400".parse().unwrap()), EExp(num("10"), num("400"))];
+        for exp in exps.iter() {
+            match evaluate(&mut env, EExp(num("2"), Box::new(exp.clone()))) {
+                Err(Overflow(op)) => assert_eq!(op, "^"),
+                result => panic!("expected an overflow, got {:?}", result),
+            }
+        }
+        let big = evaluate(&mut env, EExp(num("2"), num("100000"))).unwrap();
+        assert!(big > Number::from(0));
+    }
+
+    #[test]
+    fn test_evaluate_arithmetic_errors_in_ieee_mode() {
+        let mut env = Environment::new();
+        env.set_ieee(true);
+        let num = |s: &str| Box::new(ENum(s.parse().unwrap()));
+        let display = |env: &mut Environment, expr| evaluate(env, expr).unwrap().to_string();
+        assert_eq!(display(&mut env, EDiv(num("1"), num("0"))), "inf");
+        assert_eq!(display(&mut env, EDiv(ENeg(num("1")).into(), num("0"))), "-inf");
+        assert_eq!(display(&mut env, EDiv(num("0"), num("0"))), "nan");
+        assert_eq!(display(&mut env, EMod(num("1"), num("0"))), "nan");
+        assert_eq!(display(&mut env, EExp(ENeg(num("10")).into(), num("2000001"))), "-inf");
+        let sqrt = EFunCall(String::from("sqrt"), vec![ENeg(num("4"))]);
+        assert_eq!(display(&mut env, sqrt), "nan");
+        let inf_plus_one = EAdd(Box::new(EDiv(num("1"), num("0"))), num("1"));
+        assert_eq!(display(&mut env, inf_plus_one), "inf");
+        let one_over_inf = EDiv(num("1"), Box::new(EDiv(num("1"), num("0"))));
+        assert_eq!(display(&mut env, one_over_inf), "0");
+    }
+
+    #[test]
+    fn test_evaluate_bitwise_expressions() {
+        let mut env = Environment::new();
+        let num = |n: i64| Box::new(ENum(Number::from(n)));
+        assert_eq!(evaluate(&mut env, EBitAnd(num(12), num(10))).unwrap(), Number::from(8));
+        assert_eq!(evaluate(&mut env, EBitOr(num(12), num(10))).unwrap(), Number::from(14));
+        assert_eq!(evaluate(&mut env, EBitXor(num(12), num(10))).unwrap(), Number::from(6));
+        assert_eq!(evaluate(&mut env, EShl(num(1), num(40))).unwrap(), Number::from(1 << 40));
+        assert_eq!(evaluate(&mut env, EShr(num(256), num(4))).unwrap(), Number::from(16));
+    }
+
+    #[test]
+    fn test_evaluate_bitwise_expressions_reject_invalid_operands() {
+        let mut env = Environment::new();
+        let half = || Box::new(ENum("0.5".parse().unwrap()));
+        let one = || Box::new(ENum(Number::from(1)));
+        match evaluate(&mut env, EBitAnd(half(), one())) {
+            Err(NonIntegralOperand(op)) => assert_eq!(op, "&"),
+            other => panic!("unexpected result {:?}", other),
+        }
+        match evaluate(&mut env, EShl(one(), Box::new(ENeg(one())))) {
+            Err(InvalidShift) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_evaluate_comparison_expressions() {
+        let mut env = Environment::new();
+        let one = || Box::new(ENum(Number::from(1)));
+        let two = || Box::new(ENum(Number::from(2)));
+        assert_eq!(evaluate(&mut env, EEq(one(), one())).unwrap(), Number::one());
+        assert_eq!(evaluate(&mut env, ENe(one(), one())).unwrap(), Number::zero());
+        assert_eq!(evaluate(&mut env, ELt(one(), two())).unwrap(), Number::one());
+        assert_eq!(evaluate(&mut env, ELe(two(), one())).unwrap(), Number::zero());
+        assert_eq!(evaluate(&mut env, EGt(two(), one())).unwrap(), Number::one());
+        assert_eq!(evaluate(&mut env, EGe(one(), one())).unwrap(), Number::one());
+    }
+
+    #[test]
+    fn test_evaluate_logical_expressions_short_circuit() {
+        let mut env = Environment::new();
+        let zero = || Box::new(ENum(Number::zero()));
+        let undefined = || Box::new(EVar(String::from("undefined")));
+        assert_eq!(evaluate(&mut env, EAnd(zero(), undefined())).unwrap(), Number::zero());
+        assert_eq!(
+            evaluate(&mut env, EOr(Box::new(ENot(zero())), undefined())).unwrap(),
+            Number::one()
+        );
+        assert!(evaluate(&mut env, EOr(zero(), undefined())).is_err());
     }
 
     #[test]
     fn test_evaluate_let_expressions() {
         let var_name = String::from("phi");
-        let let_expr = EAdd(Box::new(ENum(1.0)), Box::new(ENum(2.0)));
+        let let_expr = EAdd(Box::new(ENum(Number::from(1))), Box::new(ENum(Number::from(2))));
         let expr = ELet(var_name.clone(), Box::new(let_expr.clone()));
         let mut env = Environment::new();
-        assert_eq!(evaluate(&mut env, expr.clone()).unwrap(), 3.0);
-        assert_eq!(env.get(var_name.clone()), Some(ComputedResult(3.0)));
+        assert_eq!(evaluate(&mut env, expr.clone()).unwrap(), Number::from(3));
+        assert_eq!(env.get(var_name.clone()), Some(ComputedResult(Number::from(3))));
     }
 
     #[test]
@@ -156,34 +631,37 @@ mod tests {
         let var_name = String::from("phi");
         let expr = ESub(
             Box::new(EAdd(
-                Box::new(ENum(20.0)),
+                Box::new(ENum(Number::from(20))),
                 Box::new(
-                    EAdd(Box::new(ENum(30.0)), Box::new(EVar(var_name.clone()))),
+                    EAdd(Box::new(ENum(Number::from(30))), Box::new(EVar(var_name.clone()))),
                 ),
             )),
-            Box::new(ENum(10.0)),
+            Box::new(ENum(Number::from(10))),
         );
         let mut env = Environment::new();
-        env.add(var_name.clone(), ComputedResult(20.0));
-        assert_eq!(evaluate(&mut env, expr).unwrap(), 60.0);
+        env.add(var_name.clone(), ComputedResult(Number::from(20)));
+        assert_eq!(evaluate(&mut env, expr).unwrap(), Number::from(60));
     }
 
     #[test]
     fn test_evaluate_simple_return_statements() {
-        let expr = EReturn(Box::new(EMul(Box::new(ENum(3.0)), Box::new(ENum(2.0)))));
+        let expr = EReturn(Box::new(EMul(
+            Box::new(ENum(Number::from(3))),
+            Box::new(ENum(Number::from(2))),
+        )));
         let mut env = Environment::new();
-        assert_eq!(evaluate(&mut env, expr).unwrap(), 6.0);
+        assert_eq!(evaluate(&mut env, expr).unwrap(), Number::from(6));
     }
 
     #[test]
     fn test_evaluate_return_statements_that_use_environment() {
         let var_name = String::from("phi");
         let expr = EReturn(Box::new(
-            EMul(Box::new(ENum(3.0)), Box::new(EVar(var_name.clone()))),
+            EMul(Box::new(ENum(Number::from(3))), Box::new(EVar(var_name.clone()))),
         ));
         let mut env = Environment::new();
-        env.add(var_name.clone(), ComputedResult(2.0));
-        assert_eq!(evaluate(&mut env, expr).unwrap(), 6.0);
+        env.add(var_name.clone(), ComputedResult(Number::from(2)));
+        assert_eq!(evaluate(&mut env, expr).unwrap(), Number::from(6));
     }
 
     #[test]
@@ -204,7 +682,7 @@ mod tests {
             env.get(String::from("square")),
             Some(LambdaRef(lambda.clone()))
         );
-        assert_eq!(result, 0.0);
+        assert_eq!(result, Number::zero());
     }
 
     #[test]
@@ -227,21 +705,90 @@ mod tests {
         let mut env = Environment::new();
         env.add(fun_name.clone(), LambdaRef(lambda));
 
-        let first_arg_expr = EMul(Box::new(ENum(2.0)), Box::new(ENum(3.0)));
-        let fun_call_expr = EFunCall(fun_name.clone(), vec![first_arg_expr, ENum(4.0)]);
+        let first_arg_expr = EMul(Box::new(ENum(Number::from(2))), Box::new(ENum(Number::from(3))));
+        let fun_call_expr = EFunCall(fun_name.clone(), vec![first_arg_expr, ENum(Number::from(4))]);
 
-        assert_eq!(evaluate(&mut env, fun_call_expr).unwrap(), 24.0);
+        assert_eq!(evaluate(&mut env, fun_call_expr).unwrap(), Number::from(24));
     }
 
     #[test]
     fn test_evaluate_native_function_calls() {
         let fun_call_expr = EFunCall(
             String::from("sqrt"),
-            vec![EMul(Box::new(ENum(3.0)), Box::new(ENum(3.0)))],
+            vec![EMul(Box::new(ENum(Number::from(3))), Box::new(ENum(Number::from(3))))],
         );
         let mut env = Environment::new();
 
-        assert_eq!(evaluate(&mut env, fun_call_expr).unwrap(), 3.0);
+        assert_eq!(evaluate(&mut env, fun_call_expr).unwrap(), Number::from(3));
+    }
+
+    #[test]
+    fn test_evaluate_ibase_and_obase_settings() {
+        let mut env = Environment::new();
+        let set = |name: &str, value: &str| {
+            EAssign(name.to_string(), Box::new(ENum(value.parse().unwrap())))
+        };
+        assert_eq!(evaluate(&mut env, EVar(String::from("ibase"))).unwrap(), Number::from(10));
+        evaluate(&mut env, set("ibase", "16.9")).unwrap();
+        evaluate(&mut env, EPostInc(String::from("obase"))).unwrap();
+        assert_eq!((env.ibase(), env.obase()), (16, 11));
+        match evaluate(&mut env, set("obase", "37")) {
+            Err(error @ InvalidSetting(..)) => {
+                assert_eq!(error.to_string(), "Runtime Error: obase can't be set to 37")
+            }
+            result => panic!("expected an invalid setting, got {:?}", result),
+        }
+        assert!(evaluate(&mut env, set("ibase", "1")).is_err());
+        assert_eq!((env.ibase(), env.obase()), (16, 11));
+        assert!(env.get(String::from("ibase")).is_none());
+    }
+
+    #[test]
+    fn test_evaluate_native_functions_with_any_arity() {
+        let mut env = Environment::new();
+        let offset = Number::from(100);
+        env.define_native("shifted_sum", Arity::AtLeast(1), move |args, _| {
+            Ok(args.iter().fold(offset.clone(), |sum, arg| sum.add(arg)))
+        });
+        env.define_native("answer", Arity::Exactly(0), |_, _| Ok(Number::from(42)));
+        let call = |name: &str, count: i64| {
+            EFunCall(name.to_string(), (1..count + 1).map(|n| ENum(Number::from(n))).collect())
+        };
+        assert_eq!(evaluate(&mut env, call("shifted_sum", 3)).unwrap(), Number::from(106));
+        assert_eq!(evaluate(&mut env, call("answer", 0)).unwrap(), Number::from(42));
+        match evaluate(&mut env, call("shifted_sum", 0)) {
+            Err(error @ InvalidArgumentCount(..)) => assert_eq!(
+                error.to_string(),
+                "Syntax Error: Function 'shifted_sum' expects at least 1 argument, but got 0"
+            ),
+            result => panic!("expected an argument count error, got {:?}", result),
+        }
+        match evaluate(&mut env, call("sqrt", 2)) {
+            Err(InvalidArgumentCount(_, Arity::Exactly(1), 2)) => (),
+            result => panic!("expected an argument count error, got {:?}", result),
+        }
+        match evaluate(&mut env, call("sqrt", 0)) {
+            Err(InvalidArgumentCount(_, Arity::Exactly(1), 0)) => (),
+            result => panic!("expected an argument count error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_evaluate_native_functions_choose_their_ieee_value() {
+        let mut env = Environment::new();
+        env.define_native("plunge", Arity::Exactly(0), |_, _| {
+            let overflow = Overflow(String::from("plunge"));
+            Err(WithIeeeValue(Box::new(overflow), Number::infinity(true)))
+        });
+        env.define_native("soar", Arity::Exactly(0), |_, _| Err(Overflow(String::from("soar"))));
+        let call = |name: &str| EFunCall(name.to_string(), Vec::new());
+        match evaluate(&mut env, call("plunge")) {
+            Err(Overflow(op)) => assert_eq!(op, "plunge"),
+            result => panic!("expected an overflow, got {:?}", result),
+        }
+        env.set_ieee(true);
+        assert_eq!(evaluate(&mut env, call("plunge")).unwrap(), Number::infinity(true));
+        assert_eq!(evaluate(&mut env, call("soar")).unwrap(), Number::infinity(false));
     }
 
     #[test]
@@ -249,16 +796,19 @@ mod tests {
         let if_expr = EIf(
             vec![
                 IfExpr {
-                    condition: (EVar(String::from("n")), ENum(1.0)),
-                    body: vec![EReturn(Box::new(ENum(1.0)))],
+                    condition: EEq(
+                        Box::new(EVar(String::from("n"))),
+                        Box::new(ENum(Number::from(1))),
+                    ),
+                    body: vec![EReturn(Box::new(ENum(Number::from(1))))],
                 },
             ],
-            vec![EReturn(Box::new(ENum(2.0)))],
+            vec![EReturn(Box::new(ENum(Number::from(2))))],
         );
         let mut env = Environment::new();
-        env.add(String::from("n"), ComputedResult(1.0));
+        env.add(String::from("n"), ComputedResult(Number::from(1)));
 
-        assert_eq!(evaluate(&mut env, if_expr).unwrap(), 1.0);
+        assert_eq!(evaluate(&mut env, if_expr).unwrap(), Number::from(1));
     }
 
     #[test]
@@ -266,35 +816,47 @@ mod tests {
         let if_expr = EIf(
             vec![
                 IfExpr {
-                    condition: (EVar(String::from("n")), ENum(2.0)),
-                    body: vec![EReturn(Box::new(ENum(1.0)))],
+                    condition: EEq(
+                        Box::new(EVar(String::from("n"))),
+                        Box::new(ENum(Number::from(2))),
+                    ),
+                    body: vec![EReturn(Box::new(ENum(Number::from(1))))],
                 },
             ],
-            vec![EReturn(Box::new(ENum(2.0)))],
+            vec![EReturn(Box::new(ENum(Number::from(2))))],
         );
         let mut env = Environment::new();
-        env.add(String::from("n"), ComputedResult(1.0));
+        env.add(String::from("n"), ComputedResult(Number::from(1)));
 
-        assert_eq!(evaluate(&mut env, if_expr).unwrap(), 2.0);
+        assert_eq!(evaluate(&mut env, if_expr).unwrap(), Number::from(2));
     }
 
     #[test]
     fn test_evaluate_simple_if_else_if_statements_when_if_condition_is_false() {
         let if_statement = IfExpr {
-            condition: (EVar(String::from("n")), ENum(1.0)),
-            body: vec![EReturn(Box::new(ENum(1.0)))],
+            condition: EEq(
+                Box::new(EVar(String::from("n"))),
+                Box::new(ENum(Number::from(1))),
+            ),
+            body: vec![EReturn(Box::new(ENum(Number::from(1))))],
         };
         let first_else_if = IfExpr {
-            condition: (EVar(String::from("n")), ENum(2.0)),
+            condition: EEq(
+                Box::new(EVar(String::from("n"))),
+                Box::new(ENum(Number::from(2))),
+            ),
             body: vec![
-                ELet(String::from("x"), Box::new(ENum(3.0))),
+                ELet(String::from("x"), Box::new(ENum(Number::from(3)))),
                 EReturn(Box::new(EVar(String::from("x")))),
             ],
         };
         let second_else_if = IfExpr {
-            condition: (EVar(String::from("n")), ENum(3.0)),
+            condition: EEq(
+                Box::new(EVar(String::from("n"))),
+                Box::new(ENum(Number::from(3))),
+            ),
             body: vec![
-                ELet(String::from("y"), Box::new(ENum(4.0))),
+                ELet(String::from("y"), Box::new(ENum(Number::from(4)))),
                 EReturn(Box::new(EMul(
                     Box::new(EVar(String::from("y"))),
                     Box::new(EVar(String::from("y"))),
@@ -303,12 +865,259 @@ mod tests {
         };
         let if_expr = EIf(
             vec![if_statement, first_else_if, second_else_if],
-            vec![EReturn(Box::new(ENum(2.0)))],
+            vec![EReturn(Box::new(ENum(Number::from(2))))],
+        );
+        let mut env = Environment::new();
+        env.add(String::from("n"), ComputedResult(Number::from(3)));
+
+        assert_eq!(evaluate(&mut env, if_expr).unwrap(), Number::from(16));
+    }
+
+    #[test]
+    fn test_evaluate_return_stops_function_execution() {
+        let fun_name = String::from("early");
+        let lambda = Lambda {
+            params: vec![],
+            body: vec![
+                EReturn(Box::new(ENum(Number::from(1)))),
+                EVar(String::from("undefined")),
+                ENum(Number::from(2)),
+            ],
+        };
+        let mut env = Environment::new();
+        env.add(fun_name.clone(), LambdaRef(lambda));
+
+        assert_eq!(evaluate(&mut env, EFunCall(fun_name, vec![])).unwrap(), Number::from(1));
+    }
+
+    #[test]
+    fn test_evaluate_return_from_nested_ifs() {
+        let n = || Box::new(EVar(String::from("n")));
+        let num = |value: i64| Box::new(ENum(Number::from(value)));
+        let fun_name = String::from("classify");
+        let lambda = Lambda {
+            params: vec![String::from("n")],
+            body: vec![
+                EIf(
+                    vec![
+                        IfExpr {
+                            condition: EGt(n(), num(0)),
+                            body: vec![
+                                EIf(
+                                    vec![
+                                        IfExpr {
+                                            condition: EGt(n(), num(10)),
+                                            body: vec![EReturn(num(2))],
+                                        },
+                                    ],
+                                    vec![],
+                                ),
+                                EReturn(num(1)),
+                            ],
+                        },
+                    ],
+                    vec![],
+                ),
+                ENum(Number::from(3)),
+                EReturn(num(0)),
+                ENum(Number::from(4)),
+            ],
+        };
+        let mut env = Environment::new();
+        env.add(fun_name.clone(), LambdaRef(lambda));
+        let call = |value: i64| EFunCall(fun_name.clone(), vec![ENum(Number::from(value))]);
+
+        assert_eq!(evaluate(&mut env, call(20)).unwrap(), Number::from(2));
+        assert_eq!(evaluate(&mut env, call(5)).unwrap(), Number::from(1));
+        assert_eq!(evaluate(&mut env, call(-5)).unwrap(), Number::from(0));
+    }
+
+    #[test]
+    fn test_evaluate_function_without_return_yields_last_statement() {
+        let fun_name = String::from("last");
+        let lambda = Lambda {
+            params: vec![],
+            body: vec![ENum(Number::from(1)), ENum(Number::from(2))],
+        };
+        let mut env = Environment::new();
+        env.add(fun_name.clone(), LambdaRef(lambda));
+
+        assert_eq!(evaluate(&mut env, EFunCall(fun_name, vec![])).unwrap(), Number::from(2));
+    }
+
+    fn var(name: &str) -> Box<Expr> {
+        Box::new(EVar(String::from(name)))
+    }
+
+    fn num(value: i64) -> Box<Expr> {
+        Box::new(ENum(Number::from(value)))
+    }
+
+    #[test]
+    fn test_evaluate_while_loops() {
+        let mut env = Environment::new();
+        env.add(String::from("i"), ComputedResult(Number::zero()));
+        env.add(String::from("sum"), ComputedResult(Number::zero()));
+        let while_loop = EWhile(
+            Box::new(ELt(var("i"), num(1000))),
+            vec![
+                ELet(String::from("i"), Box::new(EAdd(var("i"), num(1)))),
+                ELet(String::from("sum"), Box::new(EAdd(var("sum"), var("i")))),
+            ],
+        );
+        evaluate(&mut env, while_loop).unwrap();
+
+        assert_eq!(evaluate(&mut env, *var("sum")).unwrap(), Number::from(500500));
+    }
+
+    #[test]
+    fn test_evaluate_for_loops_with_break_and_continue() {
+        let mut env = Environment::new();
+        env.add(String::from("sum"), ComputedResult(Number::zero()));
+        let if_then = |condition: Expr, statement: Expr| {
+            EIf(vec![IfExpr { condition, body: vec![statement] }], vec![])
+        };
+        let for_loop = EFor(
+            Some(Box::new(ELet(String::from("i"), num(0)))),
+            None,
+            Some(Box::new(ELet(String::from("i"), Box::new(EAdd(var("i"), num(1)))))),
+            vec![
+                if_then(EGt(var("i"), num(10)), EBreak),
+                if_then(EEq(Box::new(EMod(var("i"), num(2))), num(1)), EContinue),
+                ELet(String::from("sum"), Box::new(EAdd(var("sum"), var("i")))),
+            ],
         );
+        evaluate(&mut env, for_loop).unwrap();
+
+        assert_eq!(evaluate(&mut env, *var("sum")).unwrap(), Number::from(30));
+    }
+
+    #[test]
+    fn test_evaluate_return_from_inside_a_loop() {
+        let fun_name = String::from("first_square_above");
+        let lambda = Lambda {
+            params: vec![String::from("n")],
+            body: vec![
+                EFor(
+                    Some(Box::new(ELet(String::from("i"), num(1)))),
+                    None,
+                    Some(Box::new(ELet(String::from("i"), Box::new(EAdd(var("i"), num(1)))))),
+                    vec![
+                        EIf(
+                            vec![
+                                IfExpr {
+                                    condition: EGt(Box::new(EMul(var("i"), var("i"))), var("n")),
+                                    body: vec![EReturn(var("i"))],
+                                },
+                            ],
+                            vec![],
+                        ),
+                    ],
+                ),
+            ],
+        };
+        let mut env = Environment::new();
+        env.add(fun_name.clone(), LambdaRef(lambda));
+        let call = EFunCall(fun_name, vec![ENum(Number::from(50))]);
+
+        assert_eq!(evaluate(&mut env, call).unwrap(), Number::from(8));
+    }
+
+    #[test]
+    fn test_evaluate_break_outside_of_a_loop_is_an_error() {
+        let mut env = Environment::new();
+        match evaluate(&mut env, EBreak) {
+            Err(LoopControlOutsideLoop(keyword)) => assert_eq!(keyword, "break"),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_evaluate_assignment_updates_existing_variables() {
+        let mut env = Environment::new();
+        env.add(String::from("x"), ComputedResult(Number::from(1)));
+        let expr = EAssign(String::from("x"), Box::new(EAdd(var("x"), num(41))));
+
+        assert_eq!(evaluate(&mut env, expr).unwrap(), Number::from(42));
+        assert_eq!(env.get(String::from("x")), Some(ComputedResult(Number::from(42))));
+    }
+
+    #[test]
+    fn test_evaluate_increments_and_decrements() {
+        let mut env = Environment::new();
+        env.add(String::from("i"), ComputedResult(Number::from(5)));
+        let i = || String::from("i");
+
+        assert_eq!(evaluate(&mut env, EPostInc(i())).unwrap(), Number::from(5));
+        assert_eq!(evaluate(&mut env, EPreInc(i())).unwrap(), Number::from(7));
+        assert_eq!(evaluate(&mut env, EPostDec(i())).unwrap(), Number::from(7));
+        assert_eq!(evaluate(&mut env, EPreDec(i())).unwrap(), Number::from(5));
+        match evaluate(&mut env, EPreInc(String::from("undefined"))) {
+            Err(UndefinedVariable(name)) => assert_eq!(name, "undefined"),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_evaluate_if_branches_assign_outer_variables_and_scope_lets() {
+        let mut env = Environment::new();
+        env.add(String::from("x"), ComputedResult(Number::from(1)));
+        env.add(String::from("y"), ComputedResult(Number::from(1)));
+        let if_expr = EIf(
+            vec![
+                IfExpr {
+                    condition: *num(1),
+                    body: vec![
+                        EAssign(String::from("x"), num(2)),
+                        ELet(String::from("y"), num(2)),
+                        ELet(String::from("z"), num(2)),
+                    ],
+                },
+            ],
+            vec![],
+        );
+        evaluate(&mut env, if_expr).unwrap();
+
+        assert_eq!(env.get(String::from("x")), Some(ComputedResult(Number::from(2))));
+        assert_eq!(env.get(String::from("y")), Some(ComputedResult(Number::from(1))));
+        assert_eq!(env.get(String::from("z")), None);
+    }
+
+    #[test]
+    fn test_evaluate_function_scopes() {
         let mut env = Environment::new();
-        env.add(String::from("n"), ComputedResult(3.0));
+        env.add(String::from("counter"), ComputedResult(Number::zero()));
+        env.add(String::from("shadowed"), ComputedResult(Number::zero()));
+        let bump = Lambda {
+            params: vec![String::from("by")],
+            body: vec![
+                ELet(String::from("shadowed"), var("by")),
+                EAssign(String::from("counter"), Box::new(EAdd(var("counter"), var("by")))),
+                EAssign(String::from("fresh"), var("by")),
+            ],
+        };
+        env.add(String::from("bump"), LambdaRef(bump));
+        let peek = Lambda {
+            params: vec![],
+            body: vec![EReturn(var("local"))],
+        };
+        env.add(String::from("peek"), LambdaRef(peek));
+        let caller = Lambda {
+            params: vec![String::from("local")],
+            body: vec![EReturn(Box::new(EFunCall(String::from("peek"), vec![])))],
+        };
+        env.add(String::from("caller"), LambdaRef(caller));
 
-        assert_eq!(evaluate(&mut env, if_expr).unwrap(), 16.0);
+        evaluate(&mut env, EFunCall(String::from("bump"), vec![*num(3)])).unwrap();
+        evaluate(&mut env, EFunCall(String::from("bump"), vec![*num(4)])).unwrap();
+        assert_eq!(env.get(String::from("counter")), Some(ComputedResult(Number::from(7))));
+        assert_eq!(env.get(String::from("shadowed")), Some(ComputedResult(Number::zero())));
+        assert_eq!(env.get(String::from("fresh")), None);
+        assert_eq!(env.get(String::from("by")), None);
+        match evaluate(&mut env, EFunCall(String::from("caller"), vec![*num(1)])) {
+            Err(UndefinedVariable(name)) => assert_eq!(name, "local"),
+            other => panic!("unexpected result {:?}", other),
+        }
     }
 
     #[test]
@@ -320,12 +1129,18 @@ mod tests {
                 EIf(
                     vec![
                         IfExpr {
-                            condition: (EVar(String::from("n")), ENum(1.0)),
-                            body: vec![EReturn(Box::new(ENum(1.0)))],
+                            condition: EEq(
+                                Box::new(EVar(String::from("n"))),
+                                Box::new(ENum(Number::from(1))),
+                            ),
+                            body: vec![EReturn(Box::new(ENum(Number::from(1))))],
                         },
                         IfExpr {
-                            condition: (EVar(String::from("n")), ENum(2.0)),
-                            body: vec![EReturn(Box::new(ENum(1.0)))],
+                            condition: EEq(
+                                Box::new(EVar(String::from("n"))),
+                                Box::new(ENum(Number::from(2))),
+                            ),
+                            body: vec![EReturn(Box::new(ENum(Number::from(1))))],
                         },
                     ],
                     vec![
@@ -335,7 +1150,7 @@ mod tests {
                                 vec![
                                     ESub(
                                         Box::new(EVar(String::from("n"))),
-                                        Box::new(ENum(1.0))
+                                        Box::new(ENum(Number::from(1)))
                                     ),
                                 ],
                             )),
@@ -344,7 +1159,7 @@ mod tests {
                                 vec![
                                     ESub(
                                         Box::new(EVar(String::from("n"))),
-                                        Box::new(ENum(2.0))
+                                        Box::new(ENum(Number::from(2)))
                                     ),
                                 ],
                             )),
@@ -356,8 +1171,64 @@ mod tests {
         let mut env = Environment::new();
         env.add(fun_name.clone(), LambdaRef(recursive_function));
 
-        let fun_call_expr = EFunCall(fun_name.clone(), vec![ENum(4.0)]);
+        let fun_call_expr = EFunCall(fun_name.clone(), vec![ENum(Number::from(4))]);
+
+        assert_eq!(evaluate(&mut env, fun_call_expr).unwrap(), Number::from(3));
+    }
+
+    fn at(line: usize, column: usize, expr: Expr) -> Expr {
+        ELocated(Span { line, column }, Box::new(expr))
+    }
 
-        assert_eq!(evaluate(&mut env, fun_call_expr).unwrap(), 3.0);
+    #[test]
+    fn test_evaluate_errors_point_at_the_innermost_position() {
+        let expr = at(1, 1, EAdd(num(1), Box::new(at(1, 5, EVar(String::from("psi"))))));
+        let error = evaluate(&mut Environment::new(), expr).unwrap_err();
+        assert_eq!(error.to_string(), "1:5: Undefined Variable: psi");
+        let expr = at(2, 1, EDiv(num(1), num(0)));
+        let error = evaluate(&mut Environment::new(), expr).unwrap_err();
+        assert_eq!(error.to_string(), "2:1: Runtime Error: Division by zero");
+    }
+
+    #[test]
+    fn test_evaluate_errors_in_functions_show_the_calls() {
+        let mut env = Environment::new();
+        let inner = Lambda {
+            params: vec![],
+            body: vec![at(2, 3, EReturn(Box::new(at(2, 10, EVar(String::from("psi"))))))],
+        };
+        let outer = Lambda {
+            params: vec![],
+            body: vec![at(5, 3, EFunCall(String::from("inner"), vec![]))],
+        };
+        env.add(String::from("inner"), LambdaRef(inner));
+        env.add(String::from("outer"), LambdaRef(outer));
+        let error = evaluate(&mut env, at(7, 1, EFunCall(String::from("outer"), vec![])))
+            .unwrap_err();
+        assert_eq!(
+            display_in("script.bc", Err(error), 10),
+            "script.bc:2:10: Undefined Variable: psi\n    in inner(), called at 5:3\n    \
+             in outer(), called at 7:1"
+        );
+    }
+
+    #[test]
+    fn test_evaluate_loop_control_escaping_a_function_points_at_the_statement() {
+        let mut env = Environment::new();
+        let body = vec![at(1, 14, EBreak)];
+        env.add(String::from("f"), LambdaRef(Lambda { params: vec![], body }));
+        let error = evaluate(&mut env, at(2, 1, EFunCall(String::from("f"), vec![])))
+            .unwrap_err();
+        assert_eq!(
+            display_in("t.bc", Err(error), 10),
+            "t.bc:1:14: Syntax Error: 'break' used outside of a loop\n    in f(), called at 2:1"
+        );
+    }
+
+    #[test]
+    fn test_evaluate_call_errors_point_at_the_call() {
+        let call = at(3, 4, EFunCall(String::from("nope"), vec![]));
+        let error = evaluate(&mut Environment::new(), call).unwrap_err();
+        assert_eq!(error.to_string(), "3:4: Undefined Function: nope");
     }
 }
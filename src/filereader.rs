@@ -1,127 +1,239 @@
-use nom::Producer;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
 use nom::IResult;
-use nom::Offset;
-use nom::FileProducer;
-use nom::Consumer;
-use nom::ConsumerState;
-use nom::Move;
-use nom::Input;
+use types::Error;
 use types::Expr;
-use parser::expr;
-use std::str::from_utf8;
-use std::ffi::OsString;
-
-pub enum State {
-    Beginning,
-    End,
-    Done,
-    Error,
-}
+use parser::{is_blank, skip_statement, statement, syntax_error};
 
-pub struct BCalcFileConsumer {
-    pub c_state: ConsumerState<usize, (), Move>,
-    pub state: State,
-    pub last_expr: Option<Expr>,
+/// Reads the statements of a `.bc` source one at a time.
+///
+/// The source is read a line at a time, so only the statement being parsed
+/// (and whatever follows it on the lines read so far) is ever held in memory,
+/// and multi-byte characters are never split. When a statement can't be
+/// parsed from the lines read so far, the reader keeps doubling the number of
+/// lines it reads ahead, until the statement is complete or the source ends.
+pub struct StatementReader<R> {
+    reader: R,
+    // Source text from the start of the line that parsing has reached. It
+    // always holds whole lines, except possibly for the last line of the
+    // source.
+    buffer: String,
+    // Where the text that hasn't been parsed yet starts in `buffer`, so that
+    // positions are counted from the start of the line
+    start: usize,
+    // The line of the source that `buffer` starts on
+    line: usize,
+    eof: bool,
+    ibase: u32,
 }
 
-impl BCalcFileConsumer {
-    pub fn new() -> Self {
-        BCalcFileConsumer {
-            state: State::Beginning,
-            c_state: ConsumerState::Continue(Move::Consume(0)),
-            last_expr: None,
-        }
+impl StatementReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<StatementReader<BufReader<File>>> {
+        Ok(StatementReader::new(BufReader::new(File::open(path)?)))
     }
 }
 
-pub struct BCalcFileStreamer {
-    file_producer: FileProducer,
-    consumer: BCalcFileConsumer,
-}
-
-impl BCalcFileStreamer {
-    pub fn new(file_name: OsString) -> Result<Self, String> {
-        file_name
-            .to_str()
-            .map(|file| match FileProducer::new(file, 5000) {
-                Ok(producer) => {
-                    Ok(BCalcFileStreamer {
-                        file_producer: producer,
-                        consumer: BCalcFileConsumer::new(),
-                    })
-                }
-                Err(_) => Err(format!("Could not create FileProducer for {:?}", file_name)),
-            })
-            .unwrap_or_else(|| {
-                Err(format!("Could not create FileProducer for {:?}", file_name))
-            })
+impl<R: BufRead> StatementReader<R> {
+    pub fn new(reader: R) -> StatementReader<R> {
+        StatementReader {
+            reader,
+            buffer: String::new(),
+            start: 0,
+            line: 1,
+            eof: false,
+            ibase: 10,
+        }
     }
-}
 
-impl Iterator for BCalcFileStreamer {
-    type Item = Expr;
+    /// Sets the base that literals without a prefix are read in, from the
+    /// next statement on. Callers keep it in step with the `ibase` of the
+    /// environment the statements run in.
+    pub fn set_ibase(&mut self, ibase: u32) {
+        self.ibase = ibase;
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.file_producer.apply(&mut self.consumer);
-        match self.consumer.state {
-            State::Error => None,
-            State::Done => None,
-            _ => {
-                let result = self.consumer.last_expr.clone();
-                result
+    // Reads up to `count` more lines into the buffer. Invalid UTF-8 is
+    // replaced, so that the parser reports it like any other stray character.
+    fn read_lines(&mut self, count: usize) -> io::Result<()> {
+        let mut bytes = Vec::new();
+        for _ in 0..count {
+            bytes.clear();
+            if self.reader.read_until(b'\n', &mut bytes)? == 0 {
+                self.eof = true;
+                break;
             }
+            self.buffer.push_str(&String::from_utf8_lossy(&bytes));
         }
+        Ok(())
     }
-}
 
-impl<'a> Consumer<&'a [u8], usize, (), Move> for BCalcFileConsumer {
-    fn state(&self) -> &ConsumerState<usize, (), Move> {
-        &self.c_state
+    // Marks the buffer as parsed up to `end`, dropping the lines before it
+    fn consume(&mut self, end: usize) {
+        let line_start = self.buffer[..end].rfind('\n').map_or(0, |idx| idx + 1);
+        self.line += self.buffer[..line_start].matches('\n').count();
+        self.buffer.drain(..line_start);
+        self.start = end - line_start;
     }
+}
 
-    fn handle(&mut self, input: Input<&'a [u8]>) -> &ConsumerState<usize, (), Move> {
-        match self.state {
-            State::Beginning => {
-                let end_of_file = match input {
-                    Input::Eof(_) => true,
-                    _ => false,
-                };
-                match input {
-                    Input::Empty | Input::Eof(None) => {
-                        self.state = State::Done;
-                        self.c_state = ConsumerState::Error(());
-                    }
-                    Input::Element(s1) |
-                    Input::Eof(Some(s1)) => {
-                        match expr(from_utf8(s1).unwrap()) {
-                            IResult::Error(_) => {
-                                self.state = State::End;
-                            }
-                            IResult::Incomplete(n) => {
-                                if !end_of_file {
-                                    self.c_state = ConsumerState::Continue(Move::Await(n));
-                                } else {
-                                    self.state = State::End;
-                                }
-                            }
-                            IResult::Done(i, expr) => {
-                                self.last_expr = Some(expr);
-                                self.state = State::Beginning;
-                                self.c_state =
-                                    ConsumerState::Continue(Move::Consume(s1.offset(i.as_bytes())));
-                            }
+/// Yields the statements of the source in order. A statement that fails to
+/// parse is reported as an error, after which parsing resumes with the next
+/// statement.
+impl<R: BufRead> Iterator for StatementReader<R> {
+    type Item = Result<Expr, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut lines_ahead = 1;
+        loop {
+            if is_blank(&self.buffer[self.start..]) {
+                let len = self.buffer.len();
+                self.consume(len);
+                if self.eof {
+                    return None;
+                }
+                lines_ahead = 1;
+            } else {
+                let buffered = self.buffer.len();
+                let parsed = match statement(&self.buffer, self.start, self.line, self.ibase) {
+                    // Unless the source has ended, the statement is only known
+                    // to be complete once the next one has started: it could
+                    // still be followed by an `else`, or be cut short at the
+                    // end of the buffer
+                    IResult::Done(rest, expr) => if self.eof || !is_blank(rest) {
+                        Some((buffered - rest.len(), Ok(expr)))
+                    } else {
+                        None
+                    },
+                    IResult::Incomplete(_) if !self.eof => None,
+                    _ => {
+                        let rest = skip_statement(&self.buffer[self.start..]);
+                        if self.eof || !rest.is_empty() {
+                            let error = syntax_error(&self.buffer, self.line);
+                            Some((buffered - rest.len(), Err(error)))
+                        } else {
+                            None
                         }
                     }
+                };
+                if let Some((len, result)) = parsed {
+                    self.consume(len);
+                    return Some(result);
                 }
+                lines_ahead *= 2;
             }
-            State::End => {
-                self.state = State::Done;
+            if let Err(error) = self.read_lines(lines_ahead) {
+                self.eof = true;
+                self.buffer.clear();
+                self.start = 0;
+                return Some(Err(Error::ReadError(error.to_string())));
             }
-            State::Done | State::Error => {
-                self.state = State::Error;
-                self.c_state = ConsumerState::Error(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use types::Error::*;
+    use types::Expr::*;
+    use types::Span;
+
+    fn read_all(source: &str, capacity: usize) -> Vec<Result<Expr, Error>> {
+        let reader = BufReader::with_capacity(capacity, Cursor::new(source.as_bytes().to_vec()));
+        StatementReader::new(reader).collect()
+    }
+
+    fn values(results: Vec<Result<Expr, Error>>) -> Vec<String> {
+        let mut env = ::types::Environment::new();
+        results
+            .into_iter()
+            .map(|result| result.and_then(|e| ::evaluator::evaluate(&mut env, e)))
+            .map(|result| ::types::display(result, 10))
+            .collect()
+    }
+
+    #[test]
+    fn test_reads_statements_spanning_many_lines() {
+        let source = "x = 2\n\ndefine f(n) {\n  if (n > 1) {\n    return n\n  }\n  else {\n    \
+                      return 0\n  }\n}\n\nf(x); f(1)\n";
+        assert_eq!(values(read_all(source, 1)), vec!["2", "0", "2", "0"]);
+    }
+
+    #[test]
+    fn test_reads_multibyte_characters_split_across_reads() {
+        let source = "# π ≈ 3.14159, or 22/7 — roughly\n1 + 1 /* ∑ */\n";
+        for capacity in 1..8 {
+            assert_eq!(values(read_all(source, capacity)), vec!["2"]);
+        }
+    }
+
+    #[test]
+    fn test_reads_long_sources_and_statements() {
+        let mut source = String::new();
+        for i in 0..2000 {
+            source.push_str(&format!("x{} = {}\n", i, i));
+        }
+        source.push_str("define total() {\n  let sum = 0\n");
+        for i in 0..2000 {
+            source.push_str(&format!("  sum += x{}\n", i));
+        }
+        source.push_str("  return sum\n}\ntotal()\n");
+        source.push_str(&format!("{}\n", "9".repeat(20000)));
+        let mut values = values(read_all(&source, 64));
+        assert_eq!(values.len(), 2003);
+        assert_eq!(values.pop().unwrap(), "9".repeat(20000));
+        assert_eq!(values.pop().unwrap(), "1999000");
+    }
+
+    #[test]
+    fn test_resumes_after_syntax_errors() {
+        let results = read_all("1 + * 2\n\n3\nlet = 4\n5 +", 16);
+        let lines: Vec<_> = results
+            .iter()
+            .map(|result| match *result {
+                Err(ParseError(ref error)) => Err(error.line),
+                Ok(_) => Ok(()),
+                Err(ref error) => panic!("unexpected error {}", error),
+            })
+            .collect();
+        assert_eq!(lines, vec![Err(1), Ok(()), Err(4), Err(5)]);
+    }
+
+    #[test]
+    fn test_reads_literals_in_the_ibase_of_the_environment() {
+        let mut env = ::types::Environment::new();
+        let mut reader = StatementReader::new(Cursor::new(b"ibase = 16\n1F; 0b11\n".to_vec()));
+        let mut values = Vec::new();
+        while let Some(statement) = reader.next() {
+            values.push(::evaluator::evaluate(&mut env, statement.unwrap()).unwrap().to_string());
+            reader.set_ibase(env.ibase());
+        }
+        assert_eq!(values, vec!["16", "31", "3"]);
+    }
+
+    #[test]
+    fn test_positions_count_from_the_start_of_the_line() {
+        let results = read_all("x = 1; 1 +* 2; y\n", 4);
+        match results[1] {
+            Err(ParseError(ref error)) => {
+                assert_eq!((error.line, error.column), (1, 11));
+                assert_eq!(error.source_line, "x = 1; 1 +* 2; y");
             }
-        };
-        &self.c_state
+            ref result => panic!("unexpected result {:?}", result),
+        }
+        match results[2] {
+            Ok(ELocated(span, _)) => assert_eq!(span, Span { line: 1, column: 16 }),
+            ref result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_replaces_invalid_utf8() {
+        let reader = Cursor::new(b"1\n\xff\n2\n".to_vec());
+        let results: Vec<_> = StatementReader::new(reader).collect();
+        assert_eq!(results.len(), 3);
+        assert!(results[1].is_err());
     }
 }
@@ -0,0 +1,212 @@
+use std::io::BufRead;
+use std::path::Path;
+use evaluator::evaluate;
+use filereader::StatementReader;
+use number::Number;
+use parser::parse_in_base;
+use types::{Arity, EnvValue, Environment, Error};
+use types::Error::*;
+
+/// A calculator that programs can embed, holding the variables and functions
+/// defined so far.
+///
+/// ```
+/// use basic_calculator::Interpreter;
+///
+/// let mut calc = Interpreter::new();
+/// calc.eval_str("define sq(n) { return n * n }").unwrap();
+/// let area = calc.eval_str("let r = 1.5\nsq(r) * 3").unwrap();
+/// assert_eq!(area.to_string(), "6.75");
+/// assert_eq!(calc.get_var("r").unwrap().to_string(), "1.5");
+/// ```
+#[derive(Clone)]
+pub struct Interpreter {
+    env: Environment,
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
+
+impl Interpreter {
+    /// An interpreter with only the built-in functions defined.
+    pub fn new() -> Interpreter {
+        Interpreter {
+            env: Environment::new(),
+        }
+    }
+
+    /// Sets the number of decimal digits kept by division, exponentiation and
+    /// native functions, as `bcalc --scale` does.
+    pub fn set_scale(&mut self, scale: u32) -> &mut Interpreter {
+        self.env.set_scale(scale);
+        self
+    }
+
+    /// Makes division by zero, domain errors and overflow produce infinities
+    /// and NaN instead of errors, as `bcalc --ieee` does.
+    pub fn set_ieee(&mut self, ieee: bool) -> &mut Interpreter {
+        self.env.set_ieee(ieee);
+        self
+    }
+
+    /// Defines the single-letter functions of GNU bc's math library and makes
+    /// `scale` a variable that sets the scale, as `bcalc -l` does.
+    ///
+    /// ```
+    /// # use basic_calculator::Interpreter;
+    /// let mut calc = Interpreter::new();
+    /// calc.set_bc_mathlib(true);
+    /// assert_eq!(calc.eval_str("scale = 5; 4 * a(1)").unwrap().to_string(), "3.14156");
+    /// ```
+    pub fn set_bc_mathlib(&mut self, bc_mathlib: bool) -> &mut Interpreter {
+        self.env.set_bc_mathlib(bc_mathlib);
+        self
+    }
+
+    /// Runs the statements in `source`, returning the value of the last one,
+    /// or zero if there are none. Evaluation stops at the first error.
+    ///
+    /// ```
+    /// # use basic_calculator::{Error, Interpreter};
+    /// let mut calc = Interpreter::new();
+    /// assert_eq!(calc.eval_str("x = 7; x // 2").unwrap().to_string(), "3");
+    /// match calc.eval_str("1 / 0") {
+    ///     Err(Error::Located(span, error)) => {
+    ///         assert_eq!(span.column, 3);
+    ///         assert_eq!(error.to_string(), "Runtime Error: Division by zero");
+    ///     }
+    ///     other => panic!("unexpected result {:?}", other),
+    /// }
+    /// ```
+    pub fn eval_str(&mut self, source: &str) -> Result<Number, Error> {
+        self.eval_statements(StatementReader::new(source.as_bytes()))
+    }
+
+    /// Like `eval_str`, but reads the statements from the file at `path`.
+    pub fn eval_file<P: AsRef<Path>>(&mut self, path: P) -> Result<Number, Error> {
+        let path = path.as_ref();
+        let reader = StatementReader::open(path)
+            .map_err(|error| ReadError(format!("{}: {}", path.display(), error)))?;
+        self.eval_statements(reader)
+    }
+
+    fn eval_statements<R: BufRead>(
+        &mut self,
+        mut reader: StatementReader<R>,
+    ) -> Result<Number, Error> {
+        let mut value = Number::zero();
+        reader.set_ibase(self.env.ibase());
+        while let Some(statement) = reader.next() {
+            value = evaluate(&mut self.env, statement?)?;
+            reader.set_ibase(self.env.ibase());
+        }
+        Ok(value)
+    }
+
+    /// Sets the global variable `name`, defining it if needed.
+    ///
+    /// ```
+    /// # use basic_calculator::{Interpreter, Number};
+    /// let mut calc = Interpreter::new();
+    /// calc.set_var("rate", "0.25".parse::<Number>().unwrap());
+    /// calc.set_var("hours", 12);
+    /// assert_eq!(calc.eval_str("rate * hours").unwrap().to_string(), "3.00");
+    /// ```
+    pub fn set_var<N: Into<Number>>(&mut self, name: &str, value: N) -> &mut Interpreter {
+        self.env.assign(name.to_string(), EnvValue::ComputedResult(value.into()));
+        self
+    }
+
+    /// The value of the global variable `name`, if it's defined and isn't a
+    /// function.
+    pub fn get_var(&self, name: &str) -> Option<Number> {
+        match self.env.get(name.to_string()) {
+            Some(EnvValue::ComputedResult(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Defines the function `name`, whose `body` holds the statements that go
+    /// between the braces of a `define`.
+    ///
+    /// ```
+    /// # use basic_calculator::Interpreter;
+    /// let mut calc = Interpreter::new();
+    /// calc.set_scale(2);
+    /// calc.define_fn("hyp", &["a", "b"], "return sqrt(a^2 + b^2)").unwrap();
+    /// assert_eq!(calc.eval_str("hyp(3, 4)").unwrap().to_string(), "5.00");
+    /// assert!(calc.define_fn("bad", &["x"], "return x +").is_err());
+    /// ```
+    pub fn define_fn(&mut self, name: &str, params: &[&str], body: &str) -> Result<(), Error> {
+        let source = format!("define {}({}) {{\n{}\n}}", name, params.join(", "), body);
+        let definition = parse_in_base(&source, self.env.ibase())?;
+        evaluate(&mut self.env, definition)?;
+        Ok(())
+    }
+
+    /// Defines the function `name` in Rust. `body` is called with the
+    /// evaluated arguments, once their number has been checked against
+    /// `arity`, and the scale that inexact results should be computed to. It
+    /// may capture state, and fails by returning an `Error`, typically a
+    /// `DomainError`.
+    ///
+    /// ```
+    /// # use basic_calculator::{Arity, Error, Interpreter, Number};
+    /// let mut calc = Interpreter::new();
+    /// calc.define_native("clamp", Arity::Exactly(3), |args, _| {
+    ///     let (x, lo, hi) = (&args[0], &args[1], &args[2]);
+    ///     Ok(if x < lo { lo } else if x > hi { hi } else { x }.clone())
+    /// });
+    /// let limit = Number::from(10);
+    /// calc.define_native("total", Arity::AtLeast(1), move |args, _| {
+    ///     let total = args.iter().fold(Number::from(0), |sum, arg| sum.add(arg));
+    ///     if total > limit {
+    ///         Err(Error::DomainError(String::from("total"), total))
+    ///     } else {
+    ///         Ok(total)
+    ///     }
+    /// });
+    /// assert_eq!(calc.eval_str("clamp(12, 0, 5)").unwrap().to_string(), "5");
+    /// assert_eq!(calc.eval_str("total(1, 2, 3)").unwrap().to_string(), "6");
+    /// assert!(calc.eval_str("total(9, 9)").is_err());
+    /// assert!(calc.eval_str("clamp(1, 2)").is_err());
+    /// ```
+    pub fn define_native<F>(&mut self, name: &str, arity: Arity, body: F) -> &mut Interpreter
+    where
+        F: Fn(&[Number], u32) -> Result<Number, Error> + 'static,
+    {
+        self.env.define_native(name, arity, body);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval_str_stops_at_the_first_error() {
+        let mut calc = Interpreter::new();
+        assert!(calc.eval_str("x = 1\ny = z\nx = 2").is_err());
+        assert_eq!(calc.get_var("x").unwrap().to_string(), "1");
+        assert_eq!(calc.eval_str("# nothing to do\n").unwrap().to_string(), "0");
+    }
+
+    #[test]
+    fn test_get_var_ignores_functions() {
+        let mut calc = Interpreter::new();
+        calc.define_fn("f", &[], "return 1").unwrap();
+        assert!(calc.get_var("f").is_none());
+        assert!(calc.get_var("sqrt").is_none());
+        assert!(calc.get_var("nope").is_none());
+    }
+
+    #[test]
+    fn test_eval_file_reports_missing_files() {
+        let error = Interpreter::new().eval_file("no/such/file.bc").unwrap_err();
+        assert!(error.to_string().starts_with("Read Error: no/such/file.bc: "));
+    }
+}
@@ -1,29 +1,127 @@
+//! A calculator for a small language in the spirit of bc, with
+//! arbitrary-precision decimal numbers, variables, functions and loops.
+//!
+//! Besides the `bcalc` command line, the calculator can be embedded through
+//! `Interpreter`:
+//!
+//! ```
+//! use basic_calculator::Interpreter;
+//!
+//! let mut calc = Interpreter::new();
+//! calc.set_var("principal", 1000);
+//! let total = calc.eval_str("principal * 1.05 ^ 2").unwrap();
+//! assert_eq!(total.to_string(), "1102.5000");
+//! ```
+
 #[macro_use]
 extern crate nom;
+extern crate num_bigint;
+extern crate num_traits;
+extern crate rustyline;
+extern crate serde;
+extern crate serde_json;
 
+mod number;
+mod mathlib;
 mod types;
 mod parser;
+mod printer;
 mod evaluator;
 mod repl;
 mod filereader;
+mod session;
+mod interpreter;
+
+pub use interpreter::Interpreter;
+pub use number::Number;
+pub use types::{Arity, Error, Span, SyntaxError};
 
 use std::env;
+use std::path::Path;
+use std::process;
 
 pub fn main() {
-    let args = env::args_os();
-    if args.len() == 1 {
+    let mut env = types::Environment::new();
+    let mut args = env::args_os().skip(1).peekable();
+    // Options come before the file name
+    while let Some(option) = args.peek().and_then(|arg| arg.to_str()).map(String::from) {
+        match option.as_str() {
+            "--scale" => {
+                args.next();
+                let scale = args.next()
+                    .and_then(|arg| arg.into_string().ok())
+                    .and_then(|arg| arg.parse().ok());
+                match scale {
+                    Some(scale) if scale <= number::MAX_DIGITS => {
+                        env.set_scale(scale);
+                    }
+                    Some(scale) => {
+                        let value = Number::from(i64::from(scale));
+                        eprintln!("{}", Error::InvalidSetting(String::from("scale"), value));
+                        process::exit(1);
+                    }
+                    None => {
+                        eprintln!("--scale expects a non-negative number of decimal digits");
+                        process::exit(1);
+                    }
+                }
+            }
+            "--ieee" => {
+                args.next();
+                env.set_ieee(true);
+            }
+            "-l" | "--mathlib" => {
+                args.next();
+                env.set_bc_mathlib(true);
+            }
+            "--load" => {
+                args.next();
+                let file_name = match args.next() {
+                    Some(file_name) => file_name,
+                    None => {
+                        eprintln!("--load expects the name of a saved session");
+                        process::exit(1);
+                    }
+                };
+                if let Err(error) = session::load(&mut env, &file_name) {
+                    let display_name = Path::new(&file_name).display().to_string();
+                    eprintln!("{}", types::display_in(&display_name, Err(error), 10));
+                    process::exit(1);
+                }
+            }
+            _ => break,
+        }
+    }
+    if args.peek().is_none() {
         println!(
-            "No arguments provided. Starting the REPL...\n Use Ctrl+C to quit.",
+            "No arguments provided. Starting the REPL...\n Use Ctrl+D to quit.",
         );
-        repl::init();
+        repl::init(env);
     } else {
-        //Assuming only one argument provided for now
-        let file_name = args.into_iter().nth(1).unwrap();
+        //Assuming only one file provided for now
+        let file_name = args.next().unwrap();
         println!("Parsing file {:?} and outputting the results", file_name);
-        let streamer = filereader::BCalcFileStreamer::new(file_name).unwrap();
-        let mut env = types::Environment::new();
-        for expr in streamer {
-            println!("{}\n", types::display(evaluator::evaluate(&mut env, expr)));
+        let display_name = Path::new(&file_name).display().to_string();
+        let mut reader = match filereader::StatementReader::open(&file_name) {
+            Ok(reader) => reader,
+            Err(error) => {
+                eprintln!("Couldn't open {}: {}", display_name, error);
+                process::exit(1);
+            }
+        };
+        let mut failed = false;
+        reader.set_ibase(env.ibase());
+        while let Some(statement) = reader.next() {
+            let quiet = statement.as_ref().is_ok_and(|expr| evaluator::is_quiet(&env, expr));
+            let result = statement.and_then(|expr| evaluator::evaluate(&mut env, expr));
+            failed |= result.is_err();
+            if !quiet || result.is_err() {
+                println!("{}\n", types::display_in(&display_name, result, env.obase()));
+            }
+            reader.set_ibase(env.ibase());
+        }
+        if failed {
+            process::exit(1);
         }
     }
 }
@@ -0,0 +1,705 @@
+use std::cmp::max;
+use std::f64;
+use num_bigint::BigInt;
+use num_traits::{Signed, ToPrimitive, Zero};
+use evaluator::overflows;
+use number::Number;
+use types::{Arity, Environment, Error};
+use types::Error::*;
+
+type Result = ::std::result::Result<Number, Error>;
+
+// Extra digits that approximations are carried out to, so that the digits
+// they get wrong are well beyond the scale of the result
+const GUARD_DIGITS: u32 = 10;
+
+// The most digits the integer part of an angle can have. Reducing an angle
+// takes as many digits of pi, which get slow to compute well before the
+// number of digits reaches `MAX_DIGITS`.
+const MAX_ANGLE_DIGITS: f64 = 5000.0;
+
+/// Defines the built-in functions and constants in `env`.
+///
+/// Transcendental functions (and `pi` and `e`) are computed to `scale`
+/// digits, rounding the last digit, while `sqrt` and `hypot` truncate like
+/// division does and the other functions are exact. In
+/// IEEE mode, infinities and NaN are handed over to floating point.
+pub fn define(env: &mut Environment) {
+    // Square roots are truncated to the scale, like division
+    env.define_native("sqrt", Arity::Exactly(1), |args, scale| {
+        args[0].sqrt(scale).ok_or_else(|| domain_error("sqrt", &args[0]))
+    });
+    define_approximation(env, "exp", f64::exp, exp);
+    define_approximation(env, "ln", f64::ln, ln);
+    define_approximation(env, "log10", f64::log10, |x, scale| {
+        let working_scale = scale + GUARD_DIGITS;
+        let ln10 = ln(&Number::from(10), working_scale)?;
+        divide(&ln(x, working_scale)?, &ln10, working_scale)
+    });
+    define_approximation(env, "log2", f64::log2, |x, scale| {
+        let working_scale = scale + GUARD_DIGITS;
+        let ln2 = ln(&Number::from(2), working_scale)?;
+        divide(&ln(x, working_scale)?, &ln2, working_scale)
+    });
+    define_approximation(env, "sin", f64::sin, sin);
+    define_approximation(env, "cos", f64::cos, cos);
+    define_approximation(env, "tan", f64::tan, |x, scale| {
+        let working_scale = scale + GUARD_DIGITS;
+        divide(&sin(x, working_scale)?, &cos(x, working_scale)?, working_scale)
+    });
+    define_approximation(env, "asin", f64::asin, asin);
+    define_approximation(env, "acos", f64::acos, |x, scale| {
+        let working_scale = scale + GUARD_DIGITS;
+        Ok(half_pi(working_scale).sub(&asin(x, working_scale)?))
+    });
+    define_approximation(env, "atan", f64::atan, |x, scale| Ok(atan(x, scale)));
+
+    env.define_native("atan2", Arity::Exactly(2), |args, scale| {
+        let (y, x) = (&args[0], &args[1]);
+        if !y.is_finite() || !x.is_finite() {
+            return Ok(Number::from_f64(y.to_f64().atan2(x.to_f64())));
+        }
+        let working_scale = scale + GUARD_DIGITS;
+        let angle = if x.is_zero() {
+            match (y.is_zero(), y.is_negative()) {
+                (true, _) => Number::zero(),
+                (false, false) => half_pi(working_scale),
+                (false, true) => half_pi(working_scale).neg(),
+            }
+        } else {
+            let angle = atan(&divide(y, x, working_scale)?, working_scale);
+            match (x.is_negative(), y.is_negative()) {
+                (false, _) => angle,
+                (true, false) => angle.add(&pi(working_scale)),
+                (true, true) => angle.sub(&pi(working_scale)),
+            }
+        };
+        Ok(round(&angle, scale))
+    });
+    env.define_native("hypot", Arity::Exactly(2), |args, scale| {
+        let (x, y) = (&args[0], &args[1]);
+        let working_scale = max(scale, max(x.scale(), y.scale())) * 2;
+        let sum = x.mul(x, working_scale).add(&y.mul(y, working_scale));
+        sum.sqrt(scale).ok_or_else(|| domain_error("hypot", &sum))
+    });
+
+    define_exact(env, "abs", |x| if x.is_negative() { x.neg() } else { x.clone() });
+    define_exact(env, "trunc", |x| Number::from(x.trunc()));
+    define_exact(env, "floor", |x| {
+        let int_part = Number::from(x.trunc());
+        if x.is_negative() && int_part != *x {
+            int_part.sub(&Number::one())
+        } else {
+            int_part
+        }
+    });
+    define_exact(env, "ceil", |x| {
+        let int_part = Number::from(x.trunc());
+        if !x.is_negative() && int_part != *x {
+            int_part.add(&Number::one())
+        } else {
+            int_part
+        }
+    });
+    define_exact(env, "round", |x| round(x, 0));
+    env.define_native("min", Arity::AtLeast(1), |args, _| {
+        Ok(args[1..].iter().fold(&args[0], |min, arg| if arg < min { arg } else { min }).clone())
+    });
+    env.define_native("max", Arity::AtLeast(1), |args, _| {
+        Ok(args[1..].iter().fold(&args[0], |max, arg| if arg > max { arg } else { max }).clone())
+    });
+
+    env.define_native("gcd", Arity::AtLeast(1), |args, _| {
+        let mut result = BigInt::zero();
+        for arg in args {
+            result = gcd(result, integer_arg("gcd", arg)?);
+        }
+        Ok(Number::from(result))
+    });
+    env.define_native("lcm", Arity::AtLeast(1), |args, _| {
+        let mut result = BigInt::from(1);
+        for arg in args {
+            let arg = integer_arg("lcm", arg)?;
+            if arg.is_zero() {
+                return Ok(Number::zero());
+            }
+            let divisor = gcd(result.clone(), arg.clone());
+            result = (&result / divisor * arg).abs();
+        }
+        Ok(Number::from(result))
+    });
+    env.define_native("factorial", Arity::Exactly(1), |args, _| {
+        let n = integer_arg("factorial", &args[0])?;
+        if n.is_negative() {
+            return Err(domain_error("factorial", &args[0]));
+        }
+        if overflows(factorial_digits(args[0].to_f64())) {
+            return Err(Overflow(String::from("factorial")));
+        }
+        let mut result = BigInt::from(1);
+        let mut i = BigInt::from(2);
+        while i <= n {
+            result *= &i;
+            i += 1;
+        }
+        Ok(Number::from(result))
+    });
+
+    // Functions without arguments double as constants, so that `pi` works as
+    // well as `pi()`
+    env.define_native("pi", Arity::Exactly(0), |_, scale| {
+        Ok(round(&pi(scale + GUARD_DIGITS), scale))
+    });
+    env.define_native("e", Arity::Exactly(0), |_, scale| {
+        Ok(round(&exp(&Number::one(), scale + GUARD_DIGITS)?, scale))
+    });
+}
+
+// Defines a one-argument function computed by `approximate` to
+// `GUARD_DIGITS` more digits than the scale, and then rounded to the scale.
+// `ieee` is its floating point counterpart, used for infinities and NaN.
+fn define_approximation<F>(
+    env: &mut Environment,
+    name: &'static str,
+    ieee: fn(f64) -> f64,
+    approximate: F,
+) where
+    F: Fn(&Number, u32) -> Result + 'static,
+{
+    define_approximation_with(env, name, ieee, round, approximate);
+}
+
+// Like `define_approximation`, but with `finish` bringing the result down to
+// the scale
+fn define_approximation_with<F>(
+    env: &mut Environment,
+    name: &'static str,
+    ieee: fn(f64) -> f64,
+    finish: fn(&Number, u32) -> Number,
+    approximate: F,
+) where
+    F: Fn(&Number, u32) -> Result + 'static,
+{
+    env.define_native(name, Arity::Exactly(1), move |args, scale| {
+        let x = &args[0];
+        if !x.is_finite() {
+            return Ok(Number::from_f64(ieee(x.to_f64())));
+        }
+        approximate(x, scale + GUARD_DIGITS)
+            .map(|result| finish(&result, scale))
+            .map_err(|error| renamed(error, name, x))
+    });
+}
+
+// Reports an error of a function as an error of `name`, the function that was
+// called, rather than of the one it's computed with
+fn renamed(error: Error, name: &str, x: &Number) -> Error {
+    match error {
+        DomainError(..) => domain_error(name, x),
+        Overflow(_) => Overflow(name.to_string()),
+        WithIeeeValue(error, value) => WithIeeeValue(Box::new(renamed(*error, name, x)), value),
+        error => error,
+    }
+}
+
+/// Defines the functions of GNU bc's math library, as with `bc -l`: `s`, `c`
+/// and `a` for the sine, cosine and arctangent, `l` and `e` for the natural
+/// logarithm and exponential, and `j(n, x)` for the Bessel function of
+/// integer order `n`. As in bc, results are truncated to the scale rather
+/// than rounded. `e` becomes a function, rather than the constant.
+pub fn define_bc(env: &mut Environment) {
+    let truncate = Number::with_scale;
+    define_approximation_with(env, "s", f64::sin, truncate, sin);
+    define_approximation_with(env, "c", f64::cos, truncate, cos);
+    define_approximation_with(env, "a", f64::atan, truncate, |x, scale| Ok(atan(x, scale)));
+    define_approximation_with(env, "l", f64::ln, truncate, ln);
+    define_approximation_with(env, "e", f64::exp, truncate, exp);
+    env.define_native("j", Arity::Exactly(2), |args, scale| {
+        let (n, x) = (&args[0], &args[1]);
+        if !n.is_finite() || !x.is_finite() {
+            return Ok(Number::nan());
+        }
+        // bc ignores the fractional part of the order
+        let order = n.trunc().to_i64().ok_or_else(|| domain_error("j", n))?;
+        // The series starts with a division by n!, and needs about |x| log10(e)
+        // more digits than the scale. J is bounded, so IEEE mode has no
+        // infinity to fall back on.
+        let magnitude = x.to_f64().abs();
+        if overflows(factorial_digits(order.abs() as f64))
+            || overflows(magnitude * f64::consts::LOG10_E)
+        {
+            return Err(WithIeeeValue(Box::new(Overflow(String::from("j"))), Number::nan()));
+        }
+        let result = bessel(order.abs(), x, scale + GUARD_DIGITS).with_scale(scale);
+        // J(-n, x) = (-1)^n J(n, x)
+        Ok(if order < 0 && order % 2 != 0 { result.neg() } else { result })
+    });
+}
+
+// Defines a one-argument function that computes its result exactly
+fn define_exact(env: &mut Environment, name: &str, f: fn(&Number) -> Number) {
+    env.define_native(name, Arity::Exactly(1), move |args, _| {
+        let x = &args[0];
+        Ok(if x.is_finite() { f(x) } else { x.clone() })
+    });
+}
+
+fn domain_error(name: &str, x: &Number) -> Error {
+    DomainError(name.to_string(), x.clone())
+}
+
+fn integer_arg(name: &str, x: &Number) -> ::std::result::Result<BigInt, Error> {
+    x.to_integer().ok_or_else(|| domain_error(name, x))
+}
+
+fn gcd(mut a: BigInt, mut b: BigInt) -> BigInt {
+    while !b.is_zero() {
+        let rem = &a % &b;
+        a = b;
+        b = rem;
+    }
+    a.abs()
+}
+
+fn divide(x: &Number, y: &Number, scale: u32) -> Result {
+    x.div(y, scale).ok_or(DivisionByZero)
+}
+
+/// `x` rounded to `scale` digits, with halves rounded away from zero.
+fn round(x: &Number, scale: u32) -> Number {
+    if x.scale() <= scale {
+        return x.clone();
+    }
+    let half = Number::new(BigInt::from(5), scale + 1);
+    let rounded = if x.is_negative() { x.sub(&half) } else { x.add(&half) };
+    rounded.with_scale(scale)
+}
+
+// The number of decimal digits needed for the integer part of a number with
+// the given magnitude
+fn int_digits(log10: f64) -> u32 {
+    if log10 > 0.0 {
+        (log10.ceil() as u32).saturating_add(1)
+    } else {
+        0
+    }
+}
+
+// Stirling's approximation of the number of digits of n!
+fn factorial_digits(n: f64) -> f64 {
+    n * (n / f64::consts::E).log10() + (2.0 * f64::consts::PI * n).log10() / 2.0
+}
+
+// Sums the terms of a series, each computed from the one before, until they
+// no longer make a difference at `scale`
+fn sum_series<F>(first: Number, scale: u32, next: F) -> Number
+where
+    F: Fn(&Number, u32) -> Number,
+{
+    let mut sum = first.clone();
+    let mut term = first;
+    let mut n = 1;
+    loop {
+        term = next(&term, n).with_scale(scale);
+        if term.is_zero() {
+            return sum;
+        }
+        sum = sum.add(&term);
+        n += 1;
+    }
+}
+
+fn exp(x: &Number, scale: u32) -> Result {
+    if x.is_negative() {
+        // Anything below e^-(scale + 1) vanishes at this scale
+        if x.to_f64() < -(f64::from(scale) + 1.0) * f64::consts::LN_10 {
+            return Ok(Number::zero().with_scale(scale));
+        }
+        let inverse = exp(&x.neg(), scale + GUARD_DIGITS)?;
+        return divide(&Number::one(), &inverse, scale);
+    }
+    let log10 = x.to_f64() * f64::consts::LOG10_E;
+    if overflows(log10) {
+        return Err(Overflow(String::from("exp")));
+    }
+    // e^x = (e^(x / 2^k))^(2^k), where x / 2^k is small enough for the
+    // series to converge quickly. Squaring doubles the relative error, and
+    // the digits of the integer part need to be right as well.
+    let mut halvings = 0;
+    let mut reduced = x.clone();
+    let half = Number::new(BigInt::from(5), 1);
+    while reduced > half {
+        reduced = reduced.mul(&half, reduced.scale() + 1);
+        halvings += 1;
+    }
+    let working_scale = scale + int_digits(log10) + halvings / 3 + 2;
+    let reduced = reduced.with_scale(working_scale);
+    let mut result = sum_series(Number::one(), working_scale, |term, n| {
+        term.mul(&reduced, working_scale)
+            .div(&Number::from(n as i64), working_scale)
+            .unwrap()
+    });
+    for _ in 0..halvings {
+        result = result.mul(&result, working_scale);
+    }
+    Ok(result.with_scale(scale))
+}
+
+fn ln(x: &Number, scale: u32) -> Result {
+    if x.is_zero() {
+        // ln(x) tends to -inf as x tends to 0, which IEEE mode returns
+        return Err(WithIeeeValue(Box::new(domain_error("ln", x)), Number::infinity(true)));
+    }
+    if x.is_negative() {
+        return Err(domain_error("ln", x));
+    }
+    // ln(x) = 2^k ln(x^(1 / 2^k)), where repeated square roots bring x close
+    // enough to 1 for the series to converge quickly. Small numbers lose
+    // relative precision to the square roots, and the result loses k bits.
+    let log10 = x.log10_abs();
+    let extra_digits = int_digits(-log10) + int_digits((log10.abs() * 3.33 + 1.0).log10()) + 2;
+    let working_scale = scale + extra_digits;
+    let mut roots = 0;
+    let mut reduced = x.clone();
+    let (low, high) = (Number::new(BigInt::from(5), 1), Number::from(2));
+    while reduced < low || reduced > high {
+        reduced = reduced.sqrt(working_scale).unwrap();
+        roots += 1;
+    }
+    // ln(y) = 2 atanh((y - 1) / (y + 1))
+    let one = Number::one();
+    let u = divide(&reduced.sub(&one), &reduced.add(&one), working_scale)?;
+    let u_squared = u.mul(&u, working_scale);
+    let mut power = u.clone();
+    let mut sum = u.clone();
+    let mut n = 1;
+    loop {
+        power = power.mul(&u_squared, working_scale);
+        let term = divide(&power, &Number::from(2 * n + 1), working_scale)?;
+        if term.is_zero() {
+            break;
+        }
+        sum = sum.add(&term);
+        n += 1;
+    }
+    let factor = Number::from(BigInt::from(2) << roots);
+    Ok(sum.mul(&factor, working_scale).with_scale(scale))
+}
+
+// The arctangent of 1/n, for an integer n > 1
+fn atan_inverse(n: i64, scale: u32) -> Number {
+    let n_squared = Number::from(n * n);
+    let first = divide(&Number::one(), &Number::from(n), scale).unwrap();
+    let mut power = first.clone();
+    let mut sum = first;
+    let mut k = 1;
+    loop {
+        power = power.div(&n_squared, scale).unwrap();
+        let term = power.div(&Number::from(2 * k + 1), scale).unwrap();
+        if term.is_zero() {
+            return sum;
+        }
+        sum = if k % 2 == 1 { sum.sub(&term) } else { sum.add(&term) };
+        k += 1;
+    }
+}
+
+fn pi(scale: u32) -> Number {
+    // Machin's formula: pi = 16 atan(1/5) - 4 atan(1/239)
+    let working_scale = scale + 2;
+    let pi = atan_inverse(5, working_scale)
+        .mul(&Number::from(16), 0)
+        .sub(&atan_inverse(239, working_scale).mul(&Number::from(4), 0));
+    pi.with_scale(scale)
+}
+
+fn half_pi(scale: u32) -> Number {
+    pi(scale + 1).div(&Number::from(2), scale).unwrap()
+}
+
+fn atan(x: &Number, scale: u32) -> Number {
+    if x.is_negative() {
+        return atan(&x.neg(), scale).neg();
+    }
+    let working_scale = scale + GUARD_DIGITS;
+    let one = Number::one();
+    if *x > one {
+        let inverse = one.div(x, working_scale).unwrap();
+        return half_pi(working_scale).sub(&atan(&inverse, working_scale)).with_scale(scale);
+    }
+    // atan(x) = 2 atan(x / (1 + sqrt(1 + x^2))), applied until x is small
+    let mut halvings = 0;
+    let mut reduced = x.with_scale(max(x.scale(), working_scale));
+    let fifth = Number::new(BigInt::from(2), 1);
+    while reduced > fifth {
+        let root = one.add(&reduced.mul(&reduced, working_scale)).sqrt(working_scale).unwrap();
+        reduced = reduced.div(&one.add(&root), working_scale).unwrap();
+        halvings += 1;
+    }
+    let squared = reduced.mul(&reduced, working_scale);
+    let mut power = reduced.clone();
+    let mut sum = reduced;
+    let mut k = 1;
+    loop {
+        power = power.mul(&squared, working_scale);
+        let term = power.div(&Number::from(2 * k + 1), working_scale).unwrap();
+        if term.is_zero() {
+            break;
+        }
+        sum = if k % 2 == 1 { sum.sub(&term) } else { sum.add(&term) };
+        k += 1;
+    }
+    sum.mul(&Number::from(BigInt::from(1) << halvings), working_scale).with_scale(scale)
+}
+
+fn asin(x: &Number, scale: u32) -> Result {
+    let one = Number::one();
+    let magnitude = if x.is_negative() { x.neg() } else { x.clone() };
+    if magnitude > one {
+        return Err(domain_error("asin", x));
+    }
+    let working_scale = scale + GUARD_DIGITS;
+    let angle = if magnitude == one {
+        half_pi(working_scale)
+    } else {
+        // asin(x) = atan(x / sqrt(1 - x^2))
+        let root = one.sub(&magnitude.mul(&magnitude, working_scale)).sqrt(working_scale).unwrap();
+        atan(&divide(&magnitude, &root, working_scale)?, working_scale)
+    };
+    let angle = angle.with_scale(scale);
+    Ok(if x.is_negative() { angle.neg() } else { angle })
+}
+
+// Reduces an angle to the range [-pi, pi], with enough digits of pi that the
+// reduced angle is right to `scale` digits. Angles too large to reduce are
+// reported as overflows, or as NaN in IEEE mode.
+fn reduce_angle(x: &Number, scale: u32) -> Result {
+    let log10 = x.log10_abs();
+    if log10 > MAX_ANGLE_DIGITS {
+        return Err(WithIeeeValue(Box::new(Overflow(String::from("sin"))), Number::nan()));
+    }
+    let two_pi = pi(scale + int_digits(log10) + 1).mul(&Number::from(2), 0);
+    let turns = round(&x.div(&two_pi, 1).unwrap(), 0);
+    Ok(x.sub(&turns.mul(&two_pi, two_pi.scale())).with_scale(max(scale, x.scale())))
+}
+
+fn sin(x: &Number, scale: u32) -> Result {
+    let working_scale = scale + GUARD_DIGITS;
+    let x = reduce_angle(x, working_scale)?;
+    let squared = x.mul(&x, working_scale);
+    Ok(sum_series(x.with_scale(working_scale), working_scale, |term, n| {
+        let n = n as i64;
+        term.mul(&squared, working_scale)
+            .div(&Number::from(-(2 * n) * (2 * n + 1)), working_scale)
+            .unwrap()
+    }).with_scale(scale))
+}
+
+fn cos(x: &Number, scale: u32) -> Result {
+    let working_scale = scale + GUARD_DIGITS;
+    let x = reduce_angle(x, working_scale)?;
+    let squared = x.mul(&x, working_scale);
+    Ok(sum_series(Number::one(), working_scale, |term, n| {
+        let n = n as i64;
+        term.mul(&squared, working_scale)
+            .div(&Number::from(-(2 * n - 1) * (2 * n)), working_scale)
+            .unwrap()
+    }).with_scale(scale))
+}
+
+// The Bessel function of the first kind of order n >= 0, from its series
+// J(n, x) = sum((-1)^k / (k! (n + k)!) (x / 2)^(2k + n)). The terms grow
+// up to about e^|x| before they shrink, so as many extra digits are needed to
+// make up for the cancellation.
+fn bessel(n: i64, x: &Number, scale: u32) -> Number {
+    let working_scale = scale + int_digits(x.to_f64().abs() * f64::consts::LOG10_E);
+    let half = x.mul(&Number::new(BigInt::from(5), 1), x.scale() + 1);
+    let squared = half.mul(&half, working_scale);
+    let mut factorial = Number::one();
+    for i in 2..n + 1 {
+        factorial = factorial.mul(&Number::from(i), 0);
+    }
+    let first = half.pow(&Number::from(n), working_scale).unwrap();
+    let first = divide(&first, &factorial, working_scale).unwrap();
+    sum_series(first, working_scale, |term, k| {
+        let k = k as i64;
+        term.mul(&squared, working_scale)
+            .div(&Number::from(-k * (n + k)), working_scale)
+            .unwrap()
+    }).with_scale(scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evaluator::evaluate;
+    use parser::parse;
+
+    fn eval(env: &mut Environment, source: &str) -> String {
+        match parse(source).and_then(|expr| evaluate(env, expr)) {
+            Ok(value) => value.to_string(),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_constants_are_correct_to_the_scale() {
+        let mut env = Environment::new();
+        assert_eq!(eval(&mut env, "pi"), "3.14159265358979323846");
+        assert_eq!(eval(&mut env, "e"), "2.71828182845904523536");
+        assert_eq!(eval(&mut env, "pi()"), "3.14159265358979323846");
+        env.set_scale(5);
+        assert_eq!(eval(&mut env, "pi"), "3.14159");
+        assert_eq!(eval(&mut env, "2 * pi"), "6.28318");
+    }
+
+    #[test]
+    fn test_exponentials_and_logarithms() {
+        let mut env = Environment::new();
+        assert_eq!(eval(&mut env, "exp(1)"), "2.71828182845904523536");
+        assert_eq!(eval(&mut env, "exp(0 - 2)"), "0.13533528323661269189");
+        assert_eq!(eval(&mut env, "exp(100)"), "26881171418161354484126255515800135873611118.77374192241519160862");
+        assert_eq!(eval(&mut env, "ln(2)"), "0.69314718055994530942");
+        assert_eq!(eval(&mut env, "ln(0.001)"), "-6.90775527898213705205");
+        assert_eq!(eval(&mut env, "log10(1000)"), "3.00000000000000000000");
+        assert_eq!(eval(&mut env, "log2(1024)"), "10.00000000000000000000");
+        assert_eq!(eval(&mut env, "ln(exp(5))"), "5.00000000000000000000");
+        assert_eq!(eval(&mut env, "ln(0)"), "1:1: Runtime Error: 0 is outside the domain of ln");
+        assert_eq!(
+            eval(&mut env, "exp(10000000)"),
+            "1:1: Runtime Error: Result of 'exp' is too large to compute"
+        );
+    }
+
+    #[test]
+    fn test_trigonometric_functions() {
+        let mut env = Environment::new();
+        assert_eq!(eval(&mut env, "sin(1)"), "0.84147098480789650665");
+        assert_eq!(eval(&mut env, "cos(1)"), "0.54030230586813971740");
+        assert_eq!(eval(&mut env, "tan(1)"), "1.55740772465490223051");
+        assert_eq!(eval(&mut env, "sin(100)"), "-0.50636564110975879366");
+        assert_eq!(eval(&mut env, "sin(pi)"), "0.00000000000000000000");
+        assert_eq!(eval(&mut env, "cos(0)"), "1.00000000000000000000");
+        assert_eq!(eval(&mut env, "atan(1) * 4"), "3.14159265358979323848");
+        assert_eq!(eval(&mut env, "atan(0 - 10)"), "-1.47112767430373459185");
+        assert_eq!(eval(&mut env, "asin(0.5)"), "0.52359877559829887308");
+        assert_eq!(eval(&mut env, "acos(0 - 1)"), "3.14159265358979323846");
+        assert_eq!(eval(&mut env, "atan2(0 - 1, 0 - 1)"), "-2.35619449019234492885");
+        assert_eq!(eval(&mut env, "atan2(1, 0)"), "1.57079632679489661923");
+        assert_eq!(eval(&mut env, "asin(2)"), "1:1: Runtime Error: 2 is outside the domain of asin");
+        assert_eq!(eval(&mut env, "acos(2)"), "1:1: Runtime Error: 2 is outside the domain of acos");
+        assert_eq!(
+            eval(&mut env, "tan(10^100000)"),
+            "1:1: Runtime Error: Result of 'tan' is too large to compute"
+        );
+    }
+
+    #[test]
+    fn test_rounding_functions() {
+        let mut env = Environment::new();
+        let results: Vec<String> = ["2.5", "-2.5", "2.4", "-2.4", "3"]
+            .iter()
+            .map(|x| {
+                let calls = ["abs", "floor", "ceil", "round", "trunc"]
+                    .iter()
+                    .map(|f| eval(&mut env, &format!("{}(0 + {})", f, x.replace('-', "0-"))))
+                    .collect::<Vec<String>>();
+                calls.join(" ")
+            })
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                "2.5 2 3 3 2",
+                "2.5 -3 -2 -3 -2",
+                "2.4 2 3 2 2",
+                "2.4 -3 -2 -2 -2",
+                "3 3 3 3 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_integer_and_variadic_functions() {
+        let mut env = Environment::new();
+        assert_eq!(eval(&mut env, "min(3, 1.5, 2)"), "1.5");
+        assert_eq!(eval(&mut env, "max(3, 1.5, 2)"), "3");
+        assert_eq!(eval(&mut env, "hypot(3, 4)"), "5.00000000000000000000");
+        assert_eq!(eval(&mut env, "gcd(12, 18, 0 - 8)"), "2");
+        assert_eq!(eval(&mut env, "lcm(4, 6, 10)"), "60");
+        assert_eq!(eval(&mut env, "factorial(20)"), "2432902008176640000");
+        assert_eq!(eval(&mut env, "factorial(0)"), "1");
+        assert_eq!(
+            eval(&mut env, "gcd(1.5, 3)"),
+            "1:1: Runtime Error: 1.5 is outside the domain of gcd"
+        );
+        assert_eq!(
+            eval(&mut env, "factorial(0 - 1)"),
+            "1:1: Runtime Error: -1 is outside the domain of factorial"
+        );
+        assert_eq!(
+            eval(&mut env, "factorial(10000000)"),
+            "1:1: Runtime Error: Result of 'factorial' is too large to compute"
+        );
+    }
+
+    #[test]
+    fn test_functions_follow_ieee_mode() {
+        let mut env = Environment::new();
+        env.set_ieee(true);
+        assert_eq!(eval(&mut env, "ln(0 - 1)"), "nan");
+        assert_eq!(eval(&mut env, "exp(1 / 0)"), "inf");
+        assert_eq!(eval(&mut env, "floor(0 - 1 / 0)"), "-inf");
+        assert_eq!(eval(&mut env, "sin(1 / 0)"), "nan");
+        assert_eq!(eval(&mut env, "ln(0)"), "-inf");
+        assert_eq!(eval(&mut env, "log2(0)"), "-inf");
+        assert_eq!(eval(&mut env, "cos(10^100000)"), "nan");
+    }
+
+    #[test]
+    fn test_bc_functions_truncate_to_the_scale() {
+        let mut env = Environment::new();
+        env.set_bc_mathlib(true);
+        assert_eq!(eval(&mut env, "s(1)"), "0.84147098480789650665");
+        assert_eq!(eval(&mut env, "c(1)"), "0.54030230586813971740");
+        assert_eq!(eval(&mut env, "l(2)"), "0.69314718055994530941");
+        assert_eq!(eval(&mut env, "e(1)"), "2.71828182845904523536");
+        eval(&mut env, "scale = 5");
+        assert_eq!(eval(&mut env, "4 * a(1)"), "3.14156");
+        assert_eq!(eval(&mut env, "l(0)"), "1:1: Runtime Error: 0 is outside the domain of l");
+    }
+
+    #[test]
+    fn test_bessel_functions_of_integer_order() {
+        let mut env = Environment::new();
+        env.set_bc_mathlib(true);
+        assert_eq!(eval(&mut env, "j(0, 1)"), "0.76519768655796655144");
+        assert_eq!(eval(&mut env, "j(1.9, 2.5)"), "0.49709410246427403801");
+        assert_eq!(eval(&mut env, "j(-3, 10)"), "-0.05837937930518681234");
+        assert_eq!(eval(&mut env, "j(2, 30)"), "0.07845124607326534890");
+        for call in &["j(1, 10^5000)", "j(100000000, 1)", "j(0 - 10^18, 1)"] {
+            let error = "1:1: Runtime Error: Result of 'j' is too large to compute";
+            assert_eq!(eval(&mut env, call), error);
+        }
+    }
+
+    #[test]
+    fn test_scale_is_a_variable_in_bc_mode() {
+        let mut env = Environment::new();
+        assert_eq!(eval(&mut env, "scale = 2"), "2");
+        assert_eq!(env.scale(), 20);
+        env.set_bc_mathlib(true);
+        assert_eq!(eval(&mut env, "scale"), "20");
+        eval(&mut env, "scale = 3.9");
+        assert_eq!(eval(&mut env, "1 / 3"), "0.333");
+        eval(&mut env, "scale++");
+        assert_eq!(eval(&mut env, "scale"), "4");
+        assert_eq!(eval(&mut env, "scale = 0 - 1"), "1:1: Runtime Error: scale can't be set to -1");
+        assert_eq!(
+            eval(&mut env, "scale = 4000000000"),
+            "1:1: Runtime Error: scale can't be set to 4000000000"
+        );
+        assert_eq!(eval(&mut env, "scale"), "4");
+        assert_eq!(env.scale(), 4);
+    }
+}
@@ -0,0 +1,586 @@
+use num_bigint::BigInt;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+use std::cmp::{max, min, Ordering};
+use std::convert::TryFrom;
+use std::f64;
+use std::fmt;
+use std::str::FromStr;
+
+// Extra digits carried through intermediate results of repeated operations
+// (such as exponentiation) so that truncation doesn't eat into the final scale.
+const GUARD_DIGITS: u32 = 10;
+
+/// Numbers are exact, so a result that would need more digits than this, on
+/// either side of the decimal point, is reported as an overflow rather than
+/// left to exhaust memory.
+pub const MAX_DIGITS: u32 = 1_000_000;
+
+/// An arbitrary-precision decimal number, in the spirit of bc.
+///
+/// The number is stored as an integer `value` together with a `scale`, the
+/// number of decimal digits after the decimal point, so that the number
+/// represented is `value / 10^scale`. Results are always truncated (never
+/// rounded) to their scale, which is what bc does as well.
+///
+/// In IEEE mode, numbers can also be infinite or NaN. Such numbers have no
+/// digits, and any arithmetic involving them is done in floating point.
+#[derive(Debug, Clone)]
+pub struct Number {
+    value: BigInt,
+    scale: u32,
+    non_finite: Option<f64>,
+}
+
+fn pow10(exp: u32) -> BigInt {
+    num_traits::pow(BigInt::from(10), exp as usize)
+}
+
+impl Number {
+    pub fn new(value: BigInt, scale: u32) -> Number {
+        Number {
+            value,
+            scale,
+            non_finite: None,
+        }
+    }
+
+    pub fn zero() -> Number {
+        Number::new(BigInt::zero(), 0)
+    }
+
+    pub fn one() -> Number {
+        Number::new(BigInt::one(), 0)
+    }
+
+    pub fn infinity(negative: bool) -> Number {
+        Number::from_f64(if negative { f64::NEG_INFINITY } else { f64::INFINITY })
+    }
+
+    pub fn nan() -> Number {
+        Number::from_f64(f64::NAN)
+    }
+
+    /// The decimal value of a floating point number, or an infinity or NaN.
+    pub fn from_f64(x: f64) -> Number {
+        if !x.is_finite() {
+            return Number {
+                value: BigInt::zero(),
+                scale: 0,
+                non_finite: Some(x),
+            };
+        }
+        // Rust prints floats in full, without an exponent, which is just what
+        // `from_str` expects
+        let magnitude = x.abs().to_string().parse().unwrap_or_else(|_| Number::zero());
+        if x < 0.0 {
+            magnitude.neg()
+        } else {
+            magnitude
+        }
+    }
+
+    /// The nearest floating point number, which may be infinite for numbers
+    /// too large for an `f64`.
+    pub fn to_f64(&self) -> f64 {
+        match self.non_finite {
+            Some(x) => x,
+            None => self.to_string().parse().unwrap_or(f64::NAN),
+        }
+    }
+
+    /// The number of digits after the decimal point.
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.non_finite.is_none()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.is_finite() && self.value.is_zero()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        match self.non_finite {
+            Some(x) => x < 0.0,
+            None => self.value.is_negative(),
+        }
+    }
+
+    /// An estimate of `log10(|self|)`, cheap even for huge numbers, which is
+    /// enough to tell how many digits the result of an operation would need.
+    pub fn log10_abs(&self) -> f64 {
+        if !self.is_finite() {
+            return self.to_f64().abs().log10();
+        }
+        let bits = self.value.bits();
+        let int_log = if bits < 1000 {
+            self.value.abs().to_f64().map_or(f64::INFINITY, f64::log10)
+        } else {
+            (bits - 1) as f64 * f64::consts::LOG10_2
+        };
+        int_log - f64::from(self.scale)
+    }
+
+    // Arithmetic with an infinity or NaN on either side, which is done in
+    // floating point
+    fn non_finite_op<F: Fn(f64, f64) -> f64>(&self, other: &Number, op: F) -> Option<Number> {
+        if self.is_finite() && other.is_finite() {
+            None
+        } else {
+            Some(Number::from_f64(op(self.to_f64(), other.to_f64())))
+        }
+    }
+
+    /// The underlying integer value at the requested scale, truncating any
+    /// digits that don't fit.
+    fn value_at(&self, scale: u32) -> BigInt {
+        if scale >= self.scale {
+            &self.value * pow10(scale - self.scale)
+        } else {
+            &self.value / pow10(self.scale - scale)
+        }
+    }
+
+    /// Change the scale of the number, truncating digits if it shrinks.
+    pub fn with_scale(&self, scale: u32) -> Number {
+        if !self.is_finite() {
+            return self.clone();
+        }
+        Number::new(self.value_at(scale), scale)
+    }
+
+    /// The integer part of the number, truncated towards zero.
+    pub fn trunc(&self) -> BigInt {
+        self.value_at(0)
+    }
+
+    /// The number as an integer, if it is finite and has no fractional digits.
+    pub fn to_integer(&self) -> Option<BigInt> {
+        if !self.is_finite() {
+            return None;
+        }
+        let int_part = self.trunc();
+        if self.value_at(self.scale) == &int_part * pow10(self.scale) {
+            Some(int_part)
+        } else {
+            None
+        }
+    }
+
+    pub fn neg(&self) -> Number {
+        match self.non_finite {
+            Some(x) => Number::from_f64(-x),
+            None => Number::new(-&self.value, self.scale),
+        }
+    }
+
+    pub fn add(&self, other: &Number) -> Number {
+        if let Some(result) = self.non_finite_op(other, |a, b| a + b) {
+            return result;
+        }
+        let scale = max(self.scale, other.scale);
+        Number::new(self.value_at(scale) + other.value_at(scale), scale)
+    }
+
+    pub fn sub(&self, other: &Number) -> Number {
+        if let Some(result) = self.non_finite_op(other, |a, b| a - b) {
+            return result;
+        }
+        let scale = max(self.scale, other.scale);
+        Number::new(self.value_at(scale) - other.value_at(scale), scale)
+    }
+
+    /// Multiply two numbers. As in bc, the result keeps all the digits of both
+    /// operands, but never more than `max(scale, self.scale, other.scale)`.
+    pub fn mul(&self, other: &Number, scale: u32) -> Number {
+        if let Some(result) = self.non_finite_op(other, |a, b| a * b) {
+            return result;
+        }
+        let full_scale = self.scale + other.scale;
+        let result_scale = min(full_scale, max(scale, max(self.scale, other.scale)));
+        Number::new(&self.value * &other.value, full_scale).with_scale(result_scale)
+    }
+
+    /// Divide two numbers, keeping `scale` digits after the decimal point.
+    /// Returns `None` when dividing by zero.
+    pub fn div(&self, other: &Number, scale: u32) -> Option<Number> {
+        if let Some(result) = self.non_finite_op(other, |a, b| a / b) {
+            return Some(result);
+        }
+        if other.is_zero() {
+            return None;
+        }
+        let numerator = &self.value * pow10(scale + other.scale);
+        let denominator = &other.value * pow10(self.scale);
+        Some(Number::new(numerator / denominator, scale))
+    }
+
+    /// The quotient of the division, truncated to an integer.
+    /// Returns `None` when dividing by zero.
+    pub fn int_div(&self, other: &Number) -> Option<Number> {
+        if let Some(result) = self.non_finite_op(other, |a, b| (a / b).trunc()) {
+            return Some(result);
+        }
+        if other.is_zero() {
+            return None;
+        }
+        let scale = max(self.scale, other.scale);
+        Some(Number::new(self.value_at(scale) / other.value_at(scale), 0))
+    }
+
+    /// The remainder left by `int_div`, which takes the sign of `self`.
+    /// Returns `None` when dividing by zero.
+    pub fn rem(&self, other: &Number) -> Option<Number> {
+        if let Some(result) = self.non_finite_op(other, |a, b| a % b) {
+            return Some(result);
+        }
+        if other.is_zero() {
+            return None;
+        }
+        let scale = max(self.scale, other.scale);
+        Some(Number::new(self.value_at(scale) % other.value_at(scale), scale))
+    }
+
+    /// Raise the number to an integer power. Any fractional part of the
+    /// exponent is ignored, as in bc. Returns `None` when raising zero to a
+    /// negative power.
+    pub fn pow(&self, exp: &Number, scale: u32) -> Option<Number> {
+        if let Some(result) = self.non_finite_op(exp, |a, b| a.powf(b.trunc())) {
+            return Some(result);
+        }
+        let exp = exp.trunc();
+        if exp.is_negative() {
+            let positive = Number::new(-exp, 0);
+            let working_scale = max(scale, self.scale) + GUARD_DIGITS;
+            return Number::one().div(&self.pow(&positive, working_scale)?, scale);
+        }
+        let result_scale = match exp.to_u32() {
+            Some(e) => min(self.scale.saturating_mul(e), max(scale, self.scale)),
+            None => max(scale, self.scale),
+        };
+        let working_scale = result_scale + GUARD_DIGITS;
+        let mut result = Number::one();
+        let mut base = self.clone();
+        let mut exp = exp;
+        let two = BigInt::from(2);
+        while !exp.is_zero() {
+            if (&exp % &two).is_one() {
+                result = result.mul(&base, working_scale);
+            }
+            exp /= &two;
+            if !exp.is_zero() {
+                base = base.mul(&base, working_scale);
+            }
+        }
+        Some(result.with_scale(result_scale))
+    }
+
+    /// Square root, keeping `max(scale, self.scale)` digits after the decimal
+    /// point. Returns `None` for negative numbers.
+    pub fn sqrt(&self, scale: u32) -> Option<Number> {
+        if self.is_negative() {
+            return None;
+        }
+        if !self.is_finite() {
+            return Some(self.clone());
+        }
+        let result_scale = max(scale, self.scale);
+        Some(Number::new(
+            self.value_at(result_scale * 2).sqrt(),
+            result_scale,
+        ))
+    }
+}
+
+impl Number {
+    /// Parse an unsigned literal in base `radix`, from 2 to 36, such as `FF.8`
+    /// in base 16. Digits above 9 are the letters `A` to `Z`, in either case.
+    /// As in bc, the fractional part is truncated to as many decimal digits
+    /// as it has digits in `radix`.
+    pub fn from_str_radix(s: &str, radix: u32) -> Option<Number> {
+        let (int_part, frac_part) = match s.find('.') {
+            Some(idx) => (&s[..idx], &s[idx + 1..]),
+            None => (s, ""),
+        };
+        if int_part.is_empty() && frac_part.is_empty() {
+            return None;
+        }
+        if !int_part.chars().chain(frac_part.chars()).all(|c| c.is_digit(radix)) {
+            return None;
+        }
+        let digits_value = |digits: &str| {
+            digits.chars().fold(BigInt::zero(), |value, c| {
+                value * radix + c.to_digit(radix).unwrap()
+            })
+        };
+        let scale = u32::try_from(frac_part.len()).ok()?;
+        let denominator = num_traits::pow(BigInt::from(radix), frac_part.len());
+        let frac_value = digits_value(frac_part) * pow10(scale) / denominator;
+        Some(Number::new(digits_value(int_part) * pow10(scale) + frac_value, scale))
+    }
+
+    /// Format the number in base `radix`, from 2 to 36, with the capital
+    /// letters as the digits above 9. As in bc, the fractional part gets the
+    /// fewest digits that are at least as precise as the scale, and digits
+    /// beyond those are truncated.
+    pub fn to_string_radix(&self, radix: u32) -> String {
+        if radix == 10 || !self.is_finite() {
+            return self.to_string();
+        }
+        let sign = if self.is_negative() { "-" } else { "" };
+        let magnitude = self.value.abs();
+        let unit = pow10(self.scale);
+        let int_part = (&magnitude / &unit).to_str_radix(radix).to_uppercase();
+        if self.scale == 0 {
+            return format!("{}{}", sign, int_part);
+        }
+        let mut precision = BigInt::one();
+        let mut digits = 0;
+        while precision < unit {
+            precision *= radix;
+            digits += 1;
+        }
+        let frac_part = (&magnitude % &unit * precision / &unit).to_str_radix(radix);
+        let padding = "0".repeat(digits - frac_part.len());
+        format!("{}{}.{}{}", sign, int_part, padding, frac_part.to_uppercase())
+    }
+}
+
+impl From<BigInt> for Number {
+    fn from(value: BigInt) -> Number {
+        Number::new(value, 0)
+    }
+}
+
+impl From<i64> for Number {
+    fn from(value: i64) -> Number {
+        Number::new(BigInt::from(value), 0)
+    }
+}
+
+impl FromStr for Number {
+    type Err = ();
+
+    /// Parse a decimal literal such as `42`, `3.14`, `.5`, `1e-9` or
+    /// `6.022E23`. The exponent is applied exactly, so no precision is lost.
+    /// Literals with more than `MAX_DIGITS` digits before or after the point
+    /// are rejected.
+    fn from_str(s: &str) -> ::std::result::Result<Number, ()> {
+        let (mantissa, exponent) = match s.find(['e', 'E']) {
+            Some(idx) => (&s[..idx], i64::from_str(&s[idx + 1..]).map_err(|_| ())?),
+            None => (s, 0),
+        };
+        let (int_part, frac_part) = match mantissa.find('.') {
+            Some(idx) => (&mantissa[..idx], &mantissa[idx + 1..]),
+            None => (mantissa, ""),
+        };
+        if int_part.is_empty() && frac_part.is_empty() {
+            return Err(());
+        }
+        if !int_part.chars().chain(frac_part.chars()).all(|c| c.is_ascii_digit()) {
+            return Err(());
+        }
+        let digits = format!("{}{}", int_part, frac_part);
+        let scale = (frac_part.len() as i64).checked_sub(exponent).ok_or(())?;
+        let int_digits = (digits.len() as i64).checked_sub(scale).ok_or(())?;
+        if scale > i64::from(MAX_DIGITS) || int_digits > i64::from(MAX_DIGITS) {
+            return Err(());
+        }
+        let value = BigInt::from_str(&digits).map_err(|_| ())?;
+        if scale >= 0 {
+            let scale = u32::try_from(scale).map_err(|_| ())?;
+            Ok(Number::new(value, scale))
+        } else {
+            let shift = u32::try_from(-scale).map_err(|_| ())?;
+            Ok(Number::new(value * pow10(shift), 0))
+        }
+    }
+}
+
+// NaN is unordered and unequal to everything, itself included, as in IEEE 754
+impl PartialEq for Number {
+    fn eq(&self, other: &Number) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Number) -> Option<Ordering> {
+        if !self.is_finite() || !other.is_finite() {
+            return self.to_f64().partial_cmp(&other.to_f64());
+        }
+        let scale = max(self.scale, other.scale);
+        Some(self.value_at(scale).cmp(&other.value_at(scale)))
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.non_finite {
+            Some(x) if x.is_nan() => return write!(f, "nan"),
+            Some(x) if x < 0.0 => return write!(f, "-inf"),
+            Some(_) => return write!(f, "inf"),
+            None => (),
+        }
+        let digits = self.value.abs().to_string();
+        let scale = self.scale as usize;
+        let sign = if self.is_negative() { "-" } else { "" };
+        if scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+        let digits = if digits.len() <= scale {
+            format!("{}{}", "0".repeat(scale + 1 - digits.len()), digits)
+        } else {
+            digits
+        };
+        let (int_part, frac_part) = digits.split_at(digits.len() - scale);
+        write!(f, "{}{}.{}", sign, int_part, frac_part)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num(s: &str) -> Number {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parses_and_displays_decimals() {
+        assert_eq!(num("42").to_string(), "42");
+        assert_eq!(num("3.140").to_string(), "3.140");
+        assert_eq!(num(".5").to_string(), "0.5");
+        assert_eq!(num("0").sub(&num("0.05")).to_string(), "-0.05");
+        assert!("1.2.3".parse::<Number>().is_err());
+        assert!(".".parse::<Number>().is_err());
+    }
+
+    #[test]
+    fn test_parses_scientific_notation() {
+        assert_eq!(num("1e-9").to_string(), "0.000000001");
+        assert_eq!(num("6.022E23").to_string(), "602200000000000000000000");
+        assert_eq!(num("1.5e+1").to_string(), "15");
+        assert_eq!(num("2.50e1").to_string(), "25.0");
+        assert!("1e".parse::<Number>().is_err());
+        assert!("1e+".parse::<Number>().is_err());
+        assert!("e5".parse::<Number>().is_err());
+        assert!("1e5e5".parse::<Number>().is_err());
+    }
+
+    #[test]
+    fn test_parses_other_bases() {
+        let parse = |s, radix| Number::from_str_radix(s, radix).map(|n| n.to_string());
+        assert_eq!(parse("FF", 16), Some(String::from("255")));
+        assert_eq!(parse("ff", 16), Some(String::from("255")));
+        assert_eq!(parse("101.1", 2), Some(String::from("5.5")));
+        assert_eq!(parse(".8", 16), Some(String::from("0.5")));
+        assert_eq!(parse(".1", 16), Some(String::from("0.0")));
+        assert_eq!(parse("Z", 36), Some(String::from("35")));
+        assert_eq!(parse("12", 2), None);
+        assert_eq!(parse(".", 16), None);
+        assert_eq!(parse("1.2.3", 8), None);
+    }
+
+    #[test]
+    fn test_displays_other_bases() {
+        assert_eq!(num("255").to_string_radix(16), "FF");
+        assert_eq!(num("0").sub(&num("10")).to_string_radix(2), "-1010");
+        assert_eq!(num("0.5").to_string_radix(2), "0.1000");
+        assert_eq!(num("10.25").to_string_radix(16), "A.40");
+        assert_eq!(num("0.01").to_string_radix(8), "0.005");
+        assert_eq!(num("35").to_string_radix(36), "Z");
+        assert_eq!(num("1.5").to_string_radix(10), "1.5");
+        assert_eq!(Number::nan().to_string_radix(16), "nan");
+    }
+
+    #[test]
+    fn test_compares_numbers_with_different_scales() {
+        assert_eq!(num("1.50"), num("1.5"));
+        assert!(num("1.49") < num("1.5"));
+        assert!(num("1").sub(&num("3")) < num("1"));
+    }
+
+    #[test]
+    fn test_addition_and_subtraction_keep_the_larger_scale() {
+        assert_eq!(num("1.5").add(&num("2.25")).to_string(), "3.75");
+        assert_eq!(num("1").sub(&num("0.001")).to_string(), "0.999");
+    }
+
+    #[test]
+    fn test_multiplication_is_limited_by_scale() {
+        assert_eq!(num("1.25").mul(&num("1.25"), 0).to_string(), "1.56");
+        assert_eq!(num("1.25").mul(&num("1.25"), 20).to_string(), "1.5625");
+    }
+
+    #[test]
+    fn test_division_truncates_to_scale() {
+        assert_eq!(num("1").div(&num("3"), 5).unwrap().to_string(), "0.33333");
+        assert_eq!(num("7").div(&num("2"), 0).unwrap().to_string(), "3");
+        assert_eq!(num("1").div(&num("0"), 5), None);
+    }
+
+    #[test]
+    fn test_integer_division_and_remainder() {
+        assert_eq!(num("7").int_div(&num("2")).unwrap().to_string(), "3");
+        assert_eq!(num("7.5").rem(&num("2")).unwrap().to_string(), "1.5");
+        assert_eq!(num("0").sub(&num("7")).rem(&num("3")).unwrap().to_string(), "-1");
+        assert_eq!(num("1").rem(&num("0")), None);
+    }
+
+    #[test]
+    fn test_integral_numbers() {
+        assert_eq!(num("4.000").to_integer(), Some(BigInt::from(4)));
+        assert_eq!(num("4.5").to_integer(), None);
+    }
+
+    #[test]
+    fn test_integer_powers_are_exact() {
+        assert_eq!(
+            num("2").pow(&num("100"), 0).unwrap().to_string(),
+            "1267650600228229401496703205376"
+        );
+        assert_eq!(num("2").pow(&num("0").sub(&num("2")), 3).unwrap().to_string(), "0.250");
+        assert_eq!(num("0").pow(&num("0").sub(&num("1")), 3), None);
+    }
+
+    #[test]
+    fn test_square_roots() {
+        assert_eq!(num("16").sqrt(0).unwrap().to_string(), "4");
+        assert_eq!(num("2").sqrt(10).unwrap().to_string(), "1.4142135623");
+        assert_eq!(num("0").sub(&num("2")).sqrt(10), None);
+    }
+
+    #[test]
+    fn test_estimates_magnitudes() {
+        assert!((num("1000").log10_abs() - 3.0).abs() < 1e-9);
+        assert!((num("0.001").log10_abs() + 3.0).abs() < 1e-9);
+        let huge = num("2").pow(&num("100000"), 0).unwrap();
+        assert!((huge.log10_abs() - 30102.999).abs() < 0.01);
+        assert_eq!(Number::zero().log10_abs(), f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_non_finite_arithmetic() {
+        let inf = Number::infinity(false);
+        assert_eq!(inf.to_string(), "inf");
+        assert_eq!(inf.neg().to_string(), "-inf");
+        assert_eq!(inf.add(&num("1")).to_string(), "inf");
+        assert_eq!(inf.sub(&inf).to_string(), "nan");
+        assert_eq!(num("1").div(&inf, 5).unwrap().to_string(), "0");
+        assert_eq!(num("0").sub(&num("2.5")).mul(&inf, 5).to_string(), "-inf");
+        assert_eq!(inf.to_integer(), None);
+        assert!(!inf.is_negative() && inf.neg().is_negative());
+    }
+
+    #[test]
+    #[allow(clippy::neg_cmp_op_on_partial_ord)]
+    fn test_nan_is_unordered() {
+        let nan = Number::nan();
+        assert!(nan != nan);
+        assert!(!(nan < num("1")) && !(nan >= num("1")));
+        assert!(Number::infinity(true) < num("0").sub(&num("1e100")));
+    }
+}
@@ -1,80 +1,371 @@
-use nom::{digit, alpha};
+use nom::digit;
+use nom::ErrorKind;
+use nom::IResult;
 use nom::IResult::*;
 use types::Lambda;
 use types::Error;
 use types::Error::ParseError;
+use types::Span;
+use types::SyntaxError;
 use types::Expr;
 use types::IfExpr;
 use types::Expr::*;
+use number::Number;
+use std::cell::{Cell, RefCell};
+use std::iter::Peekable;
+use std::str::CharIndices;
 use std::str::FromStr;
 
 // Use the classic solution to break left recursion in a LL(1) recursive descent parser
 // Solution can be found here: https://www.engr.mun.ca/~theo/Misc/exp_parsing.htm#classic
+//
+// Operators, from the loosest to the tightest binding:
+//   ||, &&, comparisons, |, xor, &, << >>, + -, * / // %, unary - + !, ^
 
-named!(num<&str, Expr>, map!(ws!(digit),  parse_num));
-named!(parens<&str, Expr>, ws!(delimited!(char!('('), mathexpr, char!(')'))));
-named!(operation<&str, Expr>, alt!( complete!(funcall) | map!(varname, parse_evar) | num | parens));
+// Whitespace, as far as the grammar is concerned, also includes `#` line
+// comments, `/* */` block comments and backslash-newline line continuations.
+// Newlines on their own end a statement, so only `blank` skips over them.
+named!(line_comment<&str, &str>,
+       recognize!(preceded!(char!('#'), opt!(complete!(is_not!("\n"))))));
+named!(block_comment<&str, &str>,
+       recognize!(delimited!(tag!("/*"), take_until!("*/"), tag!("*/"))));
+named!(space<&str, &str>,
+       recognize!(many0!(alt!(
+           is_a!(" \t\r") | line_comment | block_comment | complete!(tag!("\\\n"))
+       ))));
+named!(blank<&str, &str>,
+       recognize!(many0!(alt!(
+           is_a!(" \t\r\n") | line_comment | block_comment | complete!(tag!("\\\n"))
+       ))));
+// Statements at the top level can also be separated by semicolons
+named!(separators<&str, &str>,
+       recognize!(many0!(alt!(
+           is_a!(" \t\r\n;") | line_comment | block_comment | complete!(tag!("\\\n"))
+       ))));
+macro_rules! wsc (
+    ($i:expr, $($args:tt)*) => (
+        sep!($i, space, $($args)*)
+    )
+);
+
+// Identifiers are made of letters, digits and underscores, and can't start
+// with a digit. Keywords are reserved, and only match as whole words, so that
+// `letter` or `iffy` are ordinary identifiers.
+pub const KEYWORDS: &[&str] = &[
+    "let", "define", "if", "else", "while", "for", "break", "continue", "return", "xor", "auto",
+];
+named!(identifier<&str, &str>,
+       verify!(
+           recognize!(preceded!(
+               one_of!("_abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ"),
+               opt!(complete!(identifier_chars))
+           )),
+           is_not_keyword
+       ));
+named!(identifier_chars<&str, &str>, take_while1_s!(is_identifier_char));
+named!(word_end<&str, &str>, not!(identifier_chars));
+
+// nom's `alt!` throws away the errors of the branches it tried, so the parser
+// keeps its own record of the furthest point it reached before failing. Once
+// the grammar has committed to a construct (say, it has seen `if` or an
+// operator), what comes next is wrapped in `expect!` with a description of the
+// tokens it wants there, which is what ends up in the error message.
+thread_local! {
+    // The remaining input at the furthest failure, and what was expected there
+    static FURTHEST_FAILURE: RefCell<Option<(usize, Vec<&'static str>)>> =
+        const { RefCell::new(None) };
+}
+macro_rules! expect (
+    ($i:expr, $expected:expr, $submac:ident!( $($args:tt)* )) => (
+        {
+            let input = $i;
+            let result = $submac!(input, $($args)*);
+            if !result.is_done() {
+                note_expected(input, $expected);
+            }
+            result
+        }
+    );
+    ($i:expr, $expected:expr, $f:expr) => (
+        expect!($i, $expected, call!($f))
+    );
+);
+
+// Runtime errors point back into the source through `ELocated` nodes. While a
+// statement is being parsed, `SOURCE` holds the input it is parsed from, so
+// that the position of any slice of it can be worked out.
+thread_local! {
+    static SOURCE: RefCell<Option<Source>> = const { RefCell::new(None) };
+}
+macro_rules! located (
+    ($i:expr, $submac:ident!( $($args:tt)* )) => (
+        {
+            let input = $i;
+            map!(input, $submac!($($args)*), |expr| locate(input, expr))
+        }
+    );
+    ($i:expr, $f:expr) => (
+        located!($i, call!($f))
+    );
+);
+
+macro_rules! keyword (
+    ($i:expr, $keyword:expr) => (
+        complete!($i, terminated!(tag!($keyword), word_end))
+    )
+);
+
+named!(number_literal<&str, &str>,
+       recognize!(tuple!(
+           is_a!("0123456789."),
+           opt!(complete!(tuple!(
+               one_of!("eE"),
+               opt!(complete!(one_of!("+-"))),
+               opt!(complete!(digit))
+           )))
+       )));
+// Literals can be written in hexadecimal, octal or binary with a `0x`, `0o`
+// or `0b` prefix. Other literals are read in the base `ibase` is set to, which
+// `IBASE` holds while a statement is being parsed. In bases above 10, the
+// digits above 9 are capital letters, and as in bc a word made only of digits
+// of the base, such as `FF`, is a literal rather than a name. Only decimal
+// literals have an exponent.
+thread_local! {
+    static IBASE: Cell<u32> = const { Cell::new(10) };
+}
+named!(prefixed_literal<&str, Number>,
+       alt!(
+           complete!(preceded!(tag!("0x"), map_opt!(is_a!("0123456789abcdefABCDEF"), hex))) |
+           complete!(preceded!(tag!("0o"), map_opt!(is_a!("01234567"), octal))) |
+           complete!(preceded!(tag!("0b"), map_opt!(is_a!("01"), binary)))
+       ));
+named!(based_literal<&str, &str>,
+       recognize!(preceded!(
+           one_of!("0123456789."),
+           opt!(complete!(is_a!("0123456789.ABCDEFGHIJKLMNOPQRSTUVWXYZ")))
+       )));
+named!(capital_literal<&str, &str>,
+       recognize!(preceded!(
+           one_of!("ABCDEFGHIJKLMNOPQRSTUVWXYZ"),
+           opt!(complete!(is_a!("0123456789.ABCDEFGHIJKLMNOPQRSTUVWXYZ")))
+       )));
+named!(num<&str, Expr>,
+       map!(wsc!(alt!(prefixed_literal | unprefixed_literal)), ENum));
+named!(parens<&str, Expr>,
+       wsc!(delimited!(
+           char!('('),
+           expect!("an expression", orexpr),
+           expect!("')'", char!(')'))
+       )));
+named!(postfix_incdec<&str, Expr>,
+       do_parse!(
+           var_name: varname >>
+           op: wsc!(alt!(complete!(tag!("++")) | complete!(tag!("--")))) >>
+           (parse_incdec(op, var_name, false))
+       ));
+named!(prefix_incdec<&str, Expr>,
+       do_parse!(
+           op: wsc!(alt!(complete!(tag!("++")) | complete!(tag!("--")))) >>
+           var_name: varname >>
+           (parse_incdec(op, var_name, true))
+       ));
+named!(operation<&str, Expr>,
+       alt!(
+           digit_word |
+           complete!(located!(funcall)) |
+           complete!(located!(postfix_incdec)) |
+           located!(map!(varname, parse_evar)) |
+           num |
+           parens
+       ));
 named!(factor<&str, Expr>,
        do_parse!(
            op: operation >>
-           rem: many0!(tuple!(char!('^'), factor)) >>
+           rem: many0!(tuple!(tag!("^"), expect!("an expression", unary))) >>
            (parse_expr(op, rem))
        ));
+// Unary operators bind looser than `^`, so `-2^2` is `-(2^2)`
+named!(unary<&str, Expr>,
+       alt!(
+           complete!(located!(prefix_incdec)) |
+           map!(preceded!(wsc!(char!('-')), expect!("an expression", unary)), parse_neg) |
+           preceded!(wsc!(char!('+')), expect!("an expression", unary)) |
+           map!(preceded!(wsc!(char!('!')), expect!("an expression", unary)), parse_not) |
+           factor
+       ));
 named!(term<&str, Expr>,
        do_parse!(
-           f: factor >>
-           rem: many0!(tuple!(alt!(char!('*') | char!('/')), factor)) >>
+           f: unary >>
+           rem: many0!(tuple!(
+               alt!(complete!(tag!("//")) | tag!("*") | tag!("/") | tag!("%")),
+               expect!("an expression", unary)
+           )) >>
            (parse_expr(f, rem))
        ));
 named!(mathexpr<&str, Expr>,
        do_parse!(
            t: term >>
-           rem: many0!(tuple!(alt!(char!('+') | char!('-')), term)) >>
+           rem: many0!(tuple!(alt!(tag!("+") | tag!("-")), expect!("an expression", term))) >>
            (parse_expr(t, rem))
        ));
-named!(varname<&str, &str>, ws!(alpha));
+named!(shiftexpr<&str, Expr>,
+       do_parse!(
+           m: mathexpr >>
+           rem: many0!(tuple!(
+               alt!(complete!(tag!("<<")) | complete!(tag!(">>"))),
+               expect!("an expression", mathexpr)
+           )) >>
+           (parse_expr(m, rem))
+       ));
+named!(bitandexpr<&str, Expr>,
+       do_parse!(
+           s: shiftexpr >>
+           rem: many0!(tuple!(
+               terminated!(tag!("&"), not!(char!('&'))),
+               expect!("an expression", shiftexpr)
+           )) >>
+           (parse_expr(s, rem))
+       ));
+named!(bitxorexpr<&str, Expr>,
+       do_parse!(
+           b: bitandexpr >>
+           rem: many0!(tuple!(keyword!("xor"), expect!("an expression", bitandexpr))) >>
+           (parse_expr(b, rem))
+       ));
+named!(bitorexpr<&str, Expr>,
+       do_parse!(
+           b: bitxorexpr >>
+           rem: many0!(tuple!(
+               terminated!(tag!("|"), not!(char!('|'))),
+               expect!("an expression", bitxorexpr)
+           )) >>
+           (parse_expr(b, rem))
+       ));
+named!(relexpr<&str, Expr>,
+       do_parse!(
+           m: bitorexpr >>
+           rem: many0!(tuple!(
+               alt!(
+                   complete!(tag!("==")) | complete!(tag!("!=")) |
+                   complete!(tag!("<=")) | complete!(tag!(">=")) |
+                   tag!("<") | tag!(">")
+               ),
+               expect!("an expression", bitorexpr)
+           )) >>
+           (parse_expr(m, rem))
+       ));
+named!(andexpr<&str, Expr>,
+       do_parse!(
+           r: relexpr >>
+           rem: many0!(tuple!(complete!(tag!("&&")), expect!("an expression", relexpr))) >>
+           (parse_expr(r, rem))
+       ));
+named!(orexpr<&str, Expr>,
+       do_parse!(
+           a: andexpr >>
+           rem: many0!(tuple!(complete!(tag!("||")), expect!("an expression", andexpr))) >>
+           (parse_expr(a, rem))
+       ));
+named!(varname<&str, &str>, wsc!(identifier));
 named!(let_expr<&str, Expr>,
        do_parse!(
-           tag!("let") >>
-           var_name: varname >>
-           char!('=') >>
-           expr: mathexpr >>
+           keyword!("let") >>
+           var_name: expect!("a variable name", varname) >>
+           expect!("'='", char!('=')) >>
+           expr: expect!("an expression", orexpr) >>
            (parse_let(var_name, expr))
        ));
+named!(assign_op<&str, &str>,
+       alt!(
+           complete!(tag!("+=")) | complete!(tag!("-=")) | complete!(tag!("*=")) |
+           complete!(tag!("/=")) | complete!(tag!("%=")) | complete!(tag!("^=")) |
+           terminated!(tag!("="), not!(char!('=')))
+       ));
+named!(assignment<&str, Expr>,
+       do_parse!(
+           var_name: varname >>
+           op: assign_op >>
+           expr: expect!("an expression", orexpr) >>
+           (parse_assignment(var_name, op, expr))
+       ));
 named!(return_statement<&str, Expr>,
        do_parse!(
-           tag!("return") >>
-           expr: mathexpr >>
+           keyword!("return") >>
+           expr: expect!("an expression", orexpr) >>
            (parse_return(expr))
        ));
+// Statements in a block end with a semicolon or a newline, except for the
+// last one, which may also be closed by the brace itself
+named!(terminators<&str, &str>, recognize!(many1!(wsc!(alt!(char!(';') | char!('\n'))))));
+named!(statement_end<&str, &str>,
+       expect!(
+           "';', a newline or '}'",
+           alt!(terminators | recognize!(peek!(wsc!(char!('}')))))
+       ));
 named!(block<&str, Vec<Expr>>,
+       preceded!(wsc!(expect!("'{'", char!('{'))), block_rest));
+// The statements of a block after its opening brace
+named!(block_rest<&str, Vec<Expr>>,
        do_parse!(
-           ws!(char!('{')) >>
-           opt!(char!('\n')) >>
-           exprs: many0!(terminated!(ws!(nested_expr), char!(';'))) >>
-           opt!(char!('\n')) >>
-           ws!(char!('}')) >>
+           blank >>
+           exprs: many0!(terminated!(wsc!(nested_expr), statement_end)) >>
+           blank >>
+           wsc!(expect!("'}'", char!('}'))) >>
            (exprs)
        ));
-named!(arg_list<&str, Vec<&str>>, delimited!(char!('('), separated_list!(char!(','), varname), char!(')')));
+// As in bc, a function body can start by declaring its local variables with
+// `auto`, which are set to zero
+named!(auto_list<&str, Vec<&str>>,
+       do_parse!(
+           wsc!(keyword!("auto")) >>
+           names: expect!(
+               "a variable name",
+               separated_nonempty_list!(char!(','), expect!("a variable name", varname))
+           ) >>
+           statement_end >>
+           (names)
+       ));
+named!(function_body<&str, Vec<Expr>>,
+       do_parse!(
+           wsc!(expect!("'{'", char!('{'))) >>
+           blank >>
+           locals: opt!(complete!(auto_list)) >>
+           body: block_rest >>
+           (parse_function_body(locals.unwrap_or_default(), body))
+       ));
+named!(arg_list<&str, Vec<&str>>,
+       delimited!(
+           expect!("'('", char!('(')),
+           separated_list!(char!(','), varname),
+           expect!("a parameter name or ')'", char!(')'))
+       ));
 named!(defun<&str, Expr>,
        do_parse!(
-           tag!("define") >>
-           func_name: varname >>
+           keyword!("define") >>
+           func_name: expect!("a function name", varname) >>
            params: arg_list >>
-           body: block >>
+           body: function_body >>
            (parse_defun(func_name, params, body))
        ));
 named!(funcall<&str, Expr>,
        do_parse!(
            func_name: varname >>
-           args: ws!(delimited!(char!('('), separated_list!(char!(','), expr), char!(')'))) >>
+           args: wsc!(delimited!(
+               char!('('),
+               separated_list!(char!(','), orexpr),
+               expect!("an argument or ')'", char!(')'))
+           )) >>
            (parse_funcall(func_name, args))
        ));
-named!(if_cond<&str, (Expr, Expr)>, delimited!(char!('('), separated_pair!(expr, ws!(tag!("==")), expr), char!(')')));
+named!(if_cond<&str, Expr>,
+       delimited!(
+           expect!("'('", char!('(')),
+           expect!("a condition", orexpr),
+           expect!("')'", char!(')'))
+       ));
 named!(single_if<&str, IfExpr>,
        do_parse!(
-           ws!(tag!("if")) >>
+           wsc!(keyword!("if")) >>
            cond: if_cond >>
            body: block >>
            (parse_single_if(cond, body))
@@ -82,18 +373,354 @@ named!(single_if<&str, IfExpr>,
 named!(ifexpr<&str, Expr>,
        do_parse!(
            ifexpr: single_if >>
-           else_ifs: many0!(do_parse!(tag!("else") >> ifexpr: single_if >> (ifexpr))) >>
-           elseexpr: do_parse!(tag!("else") >> body: block >> (body)) >>
-           (parse_if_expression(ifexpr, else_ifs, elseexpr))
+           else_ifs: many0!(complete!(do_parse!(
+               blank >> keyword!("else") >> ifexpr: single_if >> (ifexpr)
+           ))) >>
+           elseexpr: opt!(complete!(do_parse!(
+               blank >> keyword!("else") >> body: block >> (body)
+           ))) >>
+           (parse_if_expression(ifexpr, else_ifs, elseexpr.unwrap_or_default()))
+       ));
+named!(while_loop<&str, Expr>,
+       do_parse!(
+           wsc!(keyword!("while")) >>
+           cond: if_cond >>
+           body: block >>
+           (parse_while(cond, body))
        ));
-named!(nested_expr<&str, Expr>, alt!(let_expr | ifexpr | return_statement | mathexpr));
-named!(pub expr<&str, Expr>, alt!(defun | nested_expr));
+named!(for_clause<&str, Expr>, alt!(let_expr | assignment | orexpr));
+named!(for_loop<&str, Expr>,
+       do_parse!(
+           wsc!(keyword!("for")) >>
+           expect!("'('", char!('(')) >>
+           init: opt!(for_clause) >>
+           wsc!(expect!("';'", char!(';'))) >>
+           cond: opt!(orexpr) >>
+           wsc!(expect!("';'", char!(';'))) >>
+           step: opt!(for_clause) >>
+           expect!("')'", char!(')')) >>
+           body: block >>
+           (parse_for(init, cond, step, body))
+       ));
+named!(loop_control<&str, Expr>,
+       alt!(
+           map!(wsc!(keyword!("break")), |_| EBreak) |
+           map!(wsc!(keyword!("continue")), |_| EContinue)
+       ));
+named!(nested_expr<&str, Expr>,
+       located!(alt!(
+           let_expr | ifexpr | while_loop | for_loop | loop_control | return_statement |
+           assignment | orexpr
+       )));
+named!(expr<&str, Expr>, preceded!(separators, alt!(located!(defun) | nested_expr)));
+
+struct Source {
+    // The input, borrowed from `statement` for as long as it's parsing it.
+    // Copying it instead would cost as much as the rest of the source for
+    // every statement.
+    text: *const str,
+    first_line: usize,
+    // Offsets at which lines begin, found lazily as positions are asked for
+    line_starts: Vec<usize>,
+    scanned: usize,
+}
+
+impl Source {
+    fn new(input: &str, first_line: usize) -> Source {
+        Source {
+            text: input,
+            first_line,
+            line_starts: vec![0],
+            scanned: 0,
+        }
+    }
 
+    // The position of `slice`, if it's part of the input the source was
+    // created from
+    fn span_of(&mut self, slice: &str) -> Option<Span> {
+        // SAFETY: `SOURCE` only holds a source while `statement` is parsing
+        // its input, which is the only time positions are asked for
+        let text = unsafe { &*self.text };
+        let offset = (slice.as_ptr() as usize).checked_sub(text.as_ptr() as usize)?;
+        if offset > text.len() {
+            return None;
+        }
+        if offset > self.scanned {
+            let scanned = self.scanned;
+            let newlines = text[scanned..offset].match_indices('\n');
+            self.line_starts.extend(newlines.map(|(idx, _)| scanned + idx + 1));
+            self.scanned = offset;
+        }
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        };
+        let line_start = self.line_starts[line];
+        Some(Span {
+            line: self.first_line + line,
+            column: text[line_start..offset].chars().count() + 1,
+        })
+    }
+}
+
+// Wraps `expr`, parsed from the start of `input`, with its position, unless
+// it already has one
+fn locate(input: &str, expr: Expr) -> Expr {
+    if let ELocated(..) = expr {
+        return expr;
+    }
+    let input = match space(input) {
+        Done(rest, _) => rest,
+        _ => input,
+    };
+    let span = SOURCE.with(|source| {
+        source.borrow_mut().as_mut().and_then(|source| source.span_of(input))
+    });
+    match span {
+        Some(span) => ELocated(span, Box::new(expr)),
+        None => expr,
+    }
+}
+
+/// Parses the statement at byte `start` of `input`, returning it along with
+/// the rest of the input. `input` starts on line `first_line` of its source,
+/// at the start of a line, so that positions are counted from there. Literals
+/// without a prefix are read in base `ibase`. The statement must be followed
+/// by a newline, a semicolon or the end of the input. When parsing fails,
+/// `syntax_error` explains why.
+pub fn statement(input: &str, start: usize, first_line: usize, ibase: u32) -> IResult<&str, Expr> {
+    FURTHEST_FAILURE.with(|furthest| *furthest.borrow_mut() = None);
+    SOURCE.with(|source| *source.borrow_mut() = Some(Source::new(input, first_line)));
+    IBASE.with(|cell| cell.set(ibase));
+    let input = &input[start..];
+    let result = expr(input);
+    IBASE.with(|cell| cell.set(10));
+    SOURCE.with(|source| *source.borrow_mut() = None);
+    match result {
+        Done(rest, expr) => {
+            let next = match space(rest) {
+                Done(next, _) => next,
+                _ => rest,
+            };
+            if next.is_empty() || next.starts_with('\n') || next.starts_with(';') {
+                Done(rest, expr)
+            } else {
+                note_expected(next, "an operator or the end of the statement");
+                Error(ErrorKind::Custom(0))
+            }
+        }
+        Incomplete(needed) => {
+            let at_end = FURTHEST_FAILURE.with(|furthest| {
+                furthest.borrow().as_ref().is_some_and(|&(len, _)| len == 0)
+            });
+            if !at_end {
+                note_expected("", "a statement");
+            }
+            Incomplete(needed)
+        }
+        Error(error) => {
+            let rest = match separators(input) {
+                Done(rest, _) => rest,
+                _ => input,
+            };
+            note_expected(rest, "a statement");
+            Error(error)
+        }
+    }
+}
+
+/// Whether `input` holds nothing but whitespace, comments and semicolons.
+pub fn is_blank(input: &str) -> bool {
+    match separators(input) {
+        Done(rest, _) => rest.is_empty(),
+        _ => false,
+    }
+}
+
+// The characters of source text along with their offsets, skipping comments
+// and backslash-newline line continuations. `unfinished` is set when the text
+// ends inside a block comment or right after a line continuation.
+struct CodeChars<'a> {
+    input: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    unfinished: bool,
+}
+
+impl<'a> CodeChars<'a> {
+    fn new(input: &'a str) -> CodeChars<'a> {
+        CodeChars {
+            input,
+            chars: input.char_indices().peekable(),
+            unfinished: false,
+        }
+    }
+}
+
+impl<'a> Iterator for CodeChars<'a> {
+    type Item = (usize, char);
+
+    fn next(&mut self) -> Option<(usize, char)> {
+        loop {
+            let (idx, c) = self.chars.next()?;
+            match c {
+                '#' => {
+                    while self.chars.peek().is_some_and(|&(_, c)| c != '\n') {
+                        self.chars.next();
+                    }
+                }
+                '/' if self.chars.peek().is_some_and(|&(_, c)| c == '*') => {
+                    match self.input[idx + 2..].find("*/") {
+                        Some(end) => {
+                            let comment_end = idx + 2 + end + 2;
+                            while self.chars.peek().is_some_and(|&(idx, _)| idx < comment_end) {
+                                self.chars.next();
+                            }
+                        }
+                        None => {
+                            self.unfinished = true;
+                            self.chars.by_ref().for_each(drop);
+                            return None;
+                        }
+                    }
+                }
+                '\\' if self.chars.peek().is_some_and(|&(_, c)| c == '\n') => {
+                    self.chars.next();
+                    if self.chars.peek().is_none() {
+                        self.unfinished = true;
+                    }
+                }
+                _ => return Some((idx, c)),
+            }
+        }
+    }
+}
+
+/// Skips past the statement at the start of `input`, up to and including the
+/// next newline or semicolon that isn't inside braces, so that parsing can
+/// carry on after a syntax error.
+pub fn skip_statement(input: &str) -> &str {
+    let input = match separators(input) {
+        Done(rest, _) => rest,
+        _ => input,
+    };
+    let mut depth = 0usize;
+    for (idx, c) in CodeChars::new(input) {
+        match c {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            '\n' | ';' if depth == 0 => return &input[idx + 1..],
+            _ => (),
+        }
+    }
+    ""
+}
+
+/// Whether `input` stops partway through a statement, so that more lines are
+/// needed to finish it: a brace or block comment is still open, the last line
+/// ends with a backslash, or the parser ran out of input. Newlines can't go
+/// inside parentheses, so those don't count.
+pub fn is_incomplete(input: &str) -> bool {
+    let mut depth = 0isize;
+    let mut chars = CodeChars::new(input);
+    for (_, c) in chars.by_ref() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            _ => (),
+        }
+    }
+    if chars.unfinished {
+        return true;
+    }
+    // Too many closing braces can't be fixed by reading on
+    depth > 0 || (depth == 0 && !is_blank(input) && statement(input, 0, 1, 10).is_incomplete())
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c == '_' || c.is_ascii_alphanumeric()
+}
+
+fn is_not_keyword(name: &str) -> bool {
+    !KEYWORDS.contains(&name)
+}
+
+// Records that `expected` was wanted at the start of `rest`, if no parser has
+// failed any further into the input yet
+fn note_expected(rest: &str, expected: &'static str) {
+    let rest = match space(rest) {
+        Done(rest, _) => rest,
+        _ => rest,
+    };
+    FURTHEST_FAILURE.with(|furthest| {
+        let mut furthest = furthest.borrow_mut();
+        match *furthest {
+            Some((len, _)) if len < rest.len() => {}
+            Some((len, ref mut expectations)) if len == rest.len() => {
+                if !expectations.contains(&expected) {
+                    expectations.push(expected);
+                }
+            }
+            _ => *furthest = Some((rest.len(), vec![expected])),
+        }
+    });
+}
+
+/// Parses a single statement, which may be followed by semicolons and
+/// whitespace but nothing else. On failure, the error points at the furthest
+/// position the parser reached, along with what it expected to find there.
 pub fn parse(input: &str) -> Result<Expr, Error> {
-    match expr(input) {
-        Done(_, expr) => Ok(expr),
-        _ => Err(ParseError),
+    parse_in_base(input, 10)
+}
+
+/// Like `parse`, but reads literals without a prefix in base `ibase`.
+pub fn parse_in_base(input: &str, ibase: u32) -> Result<Expr, Error> {
+    // nom reports running out of input as `Incomplete` rather than as an
+    // error at the end of the input, so make sure the statement is finished
+    let input = &if input.ends_with('\n') {
+        input.to_string()
+    } else {
+        format!("{}\n", input)
+    };
+    if let Done(rest, expr) = statement(input, 0, 1, ibase) {
+        if is_blank(rest) {
+            return Ok(expr);
+        }
+        let rest = match separators(rest) {
+            Done(rest, _) => rest,
+            _ => rest,
+        };
+        note_expected(rest, "the end of the input");
     }
+    Err(syntax_error(input, 1))
+}
+
+/// Describes why `statement` failed to parse `input`, pointing at the furthest
+/// position the parser reached along with what it expected to find there.
+pub fn syntax_error(input: &str, first_line: usize) -> Error {
+    let (rest_len, expectations) = FURTHEST_FAILURE
+        .with(|furthest| furthest.borrow_mut().take())
+        .unwrap_or((input.len(), vec![]));
+    let offset = input.len() - rest_len;
+    // Running out of input is reported at the end of the last line, rather
+    // than at the start of an empty one after the final newline
+    let offset = if offset == input.len() && input.ends_with('\n') {
+        offset - 1
+    } else {
+        offset
+    };
+    let line_start = input[..offset].rfind('\n').map_or(0, |idx| idx + 1);
+    let line_end = input[offset..].find('\n').map_or(input.len(), |idx| offset + idx);
+    let expected = match expectations.split_last() {
+        Some((last, [])) => last.to_string(),
+        Some((last, init)) => format!("{} or {}", init.join(", "), last),
+        None => String::from("a statement"),
+    };
+    ParseError(SyntaxError {
+        line: first_line + input[..offset].matches('\n').count(),
+        column: input[line_start..offset].chars().count() + 1,
+        expected,
+        source_line: input[line_start..line_end].trim_end_matches('\r').to_string(),
+    })
 }
 
 fn parse_if_expression(ifexpr: IfExpr, else_ifs: Vec<IfExpr>, elseexpr: Vec<Expr>) -> Expr {
@@ -102,7 +729,20 @@ fn parse_if_expression(ifexpr: IfExpr, else_ifs: Vec<IfExpr>, elseexpr: Vec<Expr
     EIf(ifs, elseexpr)
 }
 
-fn parse_single_if(condition: (Expr, Expr), body: Vec<Expr>) -> IfExpr {
+fn parse_while(condition: Expr, body: Vec<Expr>) -> Expr {
+    EWhile(Box::new(condition), body)
+}
+
+fn parse_for(
+    init: Option<Expr>,
+    condition: Option<Expr>,
+    step: Option<Expr>,
+    body: Vec<Expr>,
+) -> Expr {
+    EFor(init.map(Box::new), condition.map(Box::new), step.map(Box::new), body)
+}
+
+fn parse_single_if(condition: Expr, body: Vec<Expr>) -> IfExpr {
     IfExpr { condition, body }
 }
 
@@ -114,6 +754,10 @@ fn parse_return(expr: Expr) -> Expr {
     EReturn(Box::new(expr))
 }
 
+fn parse_function_body(locals: Vec<&str>, body: Vec<Expr>) -> Vec<Expr> {
+    locals.into_iter().map(|name| parse_let(name, ENum(Number::zero()))).chain(body).collect()
+}
+
 fn parse_defun(func_name: &str, params: Vec<&str>, body: Vec<Expr>) -> Expr {
     EDefun(
         func_name.to_string(),
@@ -124,6 +768,14 @@ fn parse_defun(func_name: &str, params: Vec<&str>, body: Vec<Expr>) -> Expr {
     )
 }
 
+fn parse_neg(expr: Expr) -> Expr {
+    ENeg(Box::new(expr))
+}
+
+fn parse_not(expr: Expr) -> Expr {
+    ENot(Box::new(expr))
+}
+
 fn parse_evar(var_name: &str) -> Expr {
     EVar(var_name.to_string())
 }
@@ -132,57 +784,203 @@ fn parse_let(var_name: &str, expr: Expr) -> Expr {
     ELet(var_name.to_string(), Box::new(expr))
 }
 
-fn parse_expr(expr: Expr, rem: Vec<(char, Expr)>) -> Expr {
+// Compound assignments are sugar: `x += e` is `x = x + e`
+fn parse_assignment(var_name: &str, op: &str, expr: Expr) -> Expr {
+    let value = match op {
+        "=" => expr,
+        _ => parse_op((&op[..op.len() - 1], expr), parse_evar(var_name)),
+    };
+    EAssign(var_name.to_string(), Box::new(value))
+}
+
+fn parse_incdec(op: &str, var_name: &str, prefix: bool) -> Expr {
+    let var_name = var_name.to_string();
+    match (op, prefix) {
+        ("++", true) => EPreInc(var_name),
+        ("--", true) => EPreDec(var_name),
+        ("++", false) => EPostInc(var_name),
+        _ => EPostDec(var_name),
+    }
+}
+
+fn parse_expr(expr: Expr, rem: Vec<(&str, Expr)>) -> Expr {
     rem.into_iter().fold(expr, |acc, val| parse_op(val, acc))
 }
 
-fn parse_op(tup: (char, Expr), expr1: Expr) -> Expr {
+fn parse_op(tup: (&str, Expr), expr1: Expr) -> Expr {
     let (op, expr2) = tup;
-    match op {
-        '+' => EAdd(Box::new(expr1), Box::new(expr2)),
-        '-' => ESub(Box::new(expr1), Box::new(expr2)),
-        '*' => EMul(Box::new(expr1), Box::new(expr2)),
-        '/' => EDiv(Box::new(expr1), Box::new(expr2)),
-        '^' => EExp(Box::new(expr1), Box::new(expr2)),
+    let expr = match op {
+        "+" => EAdd(Box::new(expr1), Box::new(expr2)),
+        "-" => ESub(Box::new(expr1), Box::new(expr2)),
+        "*" => EMul(Box::new(expr1), Box::new(expr2)),
+        "/" => EDiv(Box::new(expr1), Box::new(expr2)),
+        "//" => EIntDiv(Box::new(expr1), Box::new(expr2)),
+        "%" => EMod(Box::new(expr1), Box::new(expr2)),
+        "^" => EExp(Box::new(expr1), Box::new(expr2)),
+        "<<" => EShl(Box::new(expr1), Box::new(expr2)),
+        ">>" => EShr(Box::new(expr1), Box::new(expr2)),
+        "&" => EBitAnd(Box::new(expr1), Box::new(expr2)),
+        "xor" => EBitXor(Box::new(expr1), Box::new(expr2)),
+        "|" => EBitOr(Box::new(expr1), Box::new(expr2)),
+        "==" => EEq(Box::new(expr1), Box::new(expr2)),
+        "!=" => ENe(Box::new(expr1), Box::new(expr2)),
+        "<" => ELt(Box::new(expr1), Box::new(expr2)),
+        "<=" => ELe(Box::new(expr1), Box::new(expr2)),
+        ">" => EGt(Box::new(expr1), Box::new(expr2)),
+        ">=" => EGe(Box::new(expr1), Box::new(expr2)),
+        "&&" => EAnd(Box::new(expr1), Box::new(expr2)),
+        "||" => EOr(Box::new(expr1), Box::new(expr2)),
         _ => panic!("Unknown Operation"),
+    };
+    match op {
+        // Operators that can fail at runtime point at themselves
+        "/" | "//" | "%" | "^" | "<<" | ">>" | "&" | "xor" | "|" => locate(op, expr),
+        _ => expr,
+    }
+}
+
+fn unprefixed_literal(input: &str) -> IResult<&str, Number> {
+    let ibase = IBASE.with(Cell::get);
+    if ibase == 10 {
+        map_res!(input, number_literal, Number::from_str)
+    } else {
+        let result = map_opt!(input, based_literal, |digits| Number::from_str_radix(digits, ibase));
+        if let Done(..) = result {
+            return result;
+        }
+        // Point at the first digit that's too big for the base
+        if let Done(_, digits) = based_literal(input) {
+            let mut points = 0;
+            let bad_digit = digits.find(|c: char| {
+                points += (c == '.') as usize;
+                !c.is_digit(ibase) && (c != '.' || points > 1)
+            });
+            if let Some(idx) = bad_digit {
+                note_expected(&input[idx..], "a digit below ibase");
+            }
+        }
+        result
+    }
+}
+
+// A literal written with capital letters only, which would otherwise be read
+// as a name
+fn digit_word(input: &str) -> IResult<&str, Expr> {
+    let ibase = IBASE.with(Cell::get);
+    if ibase <= 10 {
+        return Error(error_position!(ErrorKind::Custom(0), input));
     }
+    map!(
+        input,
+        wsc!(map_opt!(complete!(terminated!(capital_literal, word_end)), |digits| {
+            Number::from_str_radix(digits, ibase)
+        })),
+        ENum
+    )
 }
 
-fn parse_num(num: &str) -> Expr {
-    // forgoing all error handling for now
-    ENum(f32::from_str(num).unwrap())
+fn hex(digits: &str) -> Option<Number> {
+    Number::from_str_radix(digits, 16)
+}
+
+fn octal(digits: &str) -> Option<Number> {
+    Number::from_str_radix(digits, 8)
+}
+
+fn binary(digits: &str) -> Option<Number> {
+    Number::from_str_radix(digits, 2)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // Most tests only care about the shape of the tree, so positions are
+    // stripped unless a test asks for them through `super::parse`
+    fn parse(input: &str) -> Result<Expr, Error> {
+        super::parse(input).map(without_spans)
+    }
+
+    fn without_spans(expr: Expr) -> Expr {
+        let strip = |expr: Box<Expr>| Box::new(without_spans(*expr));
+        let strip_all = |exprs: Vec<Expr>| exprs.into_iter().map(without_spans).collect();
+        match expr {
+            ELocated(_, expr) => without_spans(*expr),
+            ENeg(e) => ENeg(strip(e)),
+            ENot(e) => ENot(strip(e)),
+            EAdd(a, b) => EAdd(strip(a), strip(b)),
+            ESub(a, b) => ESub(strip(a), strip(b)),
+            EMul(a, b) => EMul(strip(a), strip(b)),
+            EDiv(a, b) => EDiv(strip(a), strip(b)),
+            EExp(a, b) => EExp(strip(a), strip(b)),
+            EMod(a, b) => EMod(strip(a), strip(b)),
+            EIntDiv(a, b) => EIntDiv(strip(a), strip(b)),
+            EBitAnd(a, b) => EBitAnd(strip(a), strip(b)),
+            EBitOr(a, b) => EBitOr(strip(a), strip(b)),
+            EBitXor(a, b) => EBitXor(strip(a), strip(b)),
+            EShl(a, b) => EShl(strip(a), strip(b)),
+            EShr(a, b) => EShr(strip(a), strip(b)),
+            EEq(a, b) => EEq(strip(a), strip(b)),
+            ENe(a, b) => ENe(strip(a), strip(b)),
+            ELt(a, b) => ELt(strip(a), strip(b)),
+            ELe(a, b) => ELe(strip(a), strip(b)),
+            EGt(a, b) => EGt(strip(a), strip(b)),
+            EGe(a, b) => EGe(strip(a), strip(b)),
+            EAnd(a, b) => EAnd(strip(a), strip(b)),
+            EOr(a, b) => EOr(strip(a), strip(b)),
+            ELet(name, e) => ELet(name, strip(e)),
+            EAssign(name, e) => EAssign(name, strip(e)),
+            EReturn(e) => EReturn(strip(e)),
+            EIf(ifs, elses) => EIf(
+                ifs.into_iter()
+                    .map(|IfExpr { condition, body }| IfExpr {
+                        condition: without_spans(condition),
+                        body: strip_all(body),
+                    })
+                    .collect(),
+                strip_all(elses),
+            ),
+            EWhile(condition, body) => EWhile(strip(condition), strip_all(body)),
+            EFor(init, condition, step, body) => EFor(
+                init.map(strip),
+                condition.map(strip),
+                step.map(strip),
+                strip_all(body),
+            ),
+            EFunCall(name, args) => EFunCall(name, strip_all(args)),
+            EDefun(name, Lambda { params, body }) => {
+                EDefun(name, Lambda { params, body: strip_all(body) })
+            }
+            expr => expr,
+        }
+    }
+
     #[test]
     fn test_parse_add_statement() {
         let parsed = parse("1 + 2").unwrap();
-        assert_eq!(parsed, EAdd(Box::new(ENum(1.0)), Box::new(ENum(2.0))));
+        assert_eq!(parsed, EAdd(Box::new(ENum(Number::from(1))), Box::new(ENum(Number::from(2)))));
     }
 
     #[test]
     fn test_parse_subtraction_statement() {
         let parsed = parse("1 - 2").unwrap();
-        assert_eq!(parsed, ESub(Box::new(ENum(1.0)), Box::new(ENum(2.0))));
+        assert_eq!(parsed, ESub(Box::new(ENum(Number::from(1))), Box::new(ENum(Number::from(2)))));
     }
 
     #[test]
     fn test_parse_multiplication_statement() {
         let parsed = parse("1 * 2").unwrap();
-        assert_eq!(parsed, EMul(Box::new(ENum(1.0)), Box::new(ENum(2.0))));
+        assert_eq!(parsed, EMul(Box::new(ENum(Number::from(1))), Box::new(ENum(Number::from(2)))));
     }
 
     #[test]
     fn test_parse_multi_level_expression() {
         let parsed = parse("1 * 2 + 3 / 4 ^ 6").unwrap();
         let expected = EAdd(
-            Box::new(EMul(Box::new(ENum(1.0)), Box::new(ENum(2.0)))),
+            Box::new(EMul(Box::new(ENum(Number::from(1))), Box::new(ENum(Number::from(2))))),
             Box::new(EDiv(
-                Box::new(ENum(3.0)),
-                Box::new(EExp(Box::new(ENum(4.0)), Box::new(ENum(6.0)))),
+                Box::new(ENum(Number::from(3))),
+                Box::new(EExp(Box::new(ENum(Number::from(4))), Box::new(ENum(Number::from(6))))),
             )),
         );
         assert_eq!(parsed, expected);
@@ -192,8 +990,8 @@ mod tests {
     fn test_parse_expression_with_parantheses() {
         let parsed = parse("(1 + 2) * 3").unwrap();
         let expected = EMul(
-            Box::new(EAdd(Box::new(ENum(1.0)), Box::new(ENum(2.0)))),
-            Box::new(ENum(3.0)),
+            Box::new(EAdd(Box::new(ENum(Number::from(1))), Box::new(ENum(Number::from(2))))),
+            Box::new(ENum(Number::from(3))),
         );
         assert_eq!(parsed, expected);
     }
@@ -201,7 +999,277 @@ mod tests {
     #[test]
     fn test_parse_division_statement() {
         let parsed = parse("1 / 2").unwrap();
-        assert_eq!(parsed, EDiv(Box::new(ENum(1.0)), Box::new(ENum(2.0))));
+        assert_eq!(parsed, EDiv(Box::new(ENum(Number::from(1))), Box::new(ENum(Number::from(2)))));
+    }
+
+    #[test]
+    fn test_parse_unary_minus_binds_looser_than_exponentiation() {
+        let parsed = parse("-2^2").unwrap();
+        assert_eq!(
+            parsed,
+            ENeg(Box::new(EExp(
+                Box::new(ENum(Number::from(2))),
+                Box::new(ENum(Number::from(2))),
+            )))
+        );
+    }
+
+    #[test]
+    fn test_parse_unary_operators_inside_expressions() {
+        let parsed = parse("2 * -3 - -x").unwrap();
+        assert_eq!(
+            parsed,
+            ESub(
+                Box::new(EMul(
+                    Box::new(ENum(Number::from(2))),
+                    Box::new(ENeg(Box::new(ENum(Number::from(3))))),
+                )),
+                Box::new(ENeg(Box::new(EVar(String::from("x"))))),
+            )
+        );
+        let parsed = parse("-(a + b) ^ +2").unwrap();
+        assert_eq!(
+            parsed,
+            ENeg(Box::new(EExp(
+                Box::new(EAdd(
+                    Box::new(EVar(String::from("a"))),
+                    Box::new(EVar(String::from("b"))),
+                )),
+                Box::new(ENum(Number::from(2))),
+            )))
+        );
+    }
+
+    #[test]
+    fn test_parse_comparison_and_logical_operators() {
+        let parsed = parse("a + 1 < b || !c && d != 2").unwrap();
+        assert_eq!(
+            parsed,
+            EOr(
+                Box::new(ELt(
+                    Box::new(EAdd(
+                        Box::new(EVar(String::from("a"))),
+                        Box::new(ENum(Number::from(1))),
+                    )),
+                    Box::new(EVar(String::from("b"))),
+                )),
+                Box::new(EAnd(
+                    Box::new(ENot(Box::new(EVar(String::from("c"))))),
+                    Box::new(ENe(
+                        Box::new(EVar(String::from("d"))),
+                        Box::new(ENum(Number::from(2))),
+                    )),
+                )),
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_comparisons_at_top_level() {
+        let parsed = parse("fib(10) == fib(9) + fib(8)").unwrap();
+        assert_eq!(
+            parsed,
+            EEq(
+                Box::new(EFunCall(String::from("fib"), vec![ENum(Number::from(10))])),
+                Box::new(EAdd(
+                    Box::new(EFunCall(String::from("fib"), vec![ENum(Number::from(9))])),
+                    Box::new(EFunCall(String::from("fib"), vec![ENum(Number::from(8))])),
+                )),
+            )
+        );
+        assert_eq!(
+            parse("x <= 2").unwrap(),
+            ELe(Box::new(EVar(String::from("x"))), Box::new(ENum(Number::from(2))))
+        );
+        assert_eq!(
+            parse("(x >= 2) * 3").unwrap(),
+            EMul(
+                Box::new(EGe(Box::new(EVar(String::from("x"))), Box::new(ENum(Number::from(2))))),
+                Box::new(ENum(Number::from(3))),
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_modulo_and_integer_division() {
+        let parsed = parse("7 % 3 // 2 / 1").unwrap();
+        assert_eq!(
+            parsed,
+            EDiv(
+                Box::new(EIntDiv(
+                    Box::new(EMod(
+                        Box::new(ENum(Number::from(7))),
+                        Box::new(ENum(Number::from(3))),
+                    )),
+                    Box::new(ENum(Number::from(2))),
+                )),
+                Box::new(ENum(Number::from(1))),
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_bitwise_operators() {
+        let parsed = parse("a | b xor c & d << 1 + 1").unwrap();
+        assert_eq!(
+            parsed,
+            EBitOr(
+                Box::new(EVar(String::from("a"))),
+                Box::new(EBitXor(
+                    Box::new(EVar(String::from("b"))),
+                    Box::new(EBitAnd(
+                        Box::new(EVar(String::from("c"))),
+                        Box::new(EShl(
+                            Box::new(EVar(String::from("d"))),
+                            Box::new(EAdd(
+                                Box::new(ENum(Number::from(1))),
+                                Box::new(ENum(Number::from(1))),
+                            )),
+                        )),
+                    )),
+                )),
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_bitwise_operators_next_to_logical_ones() {
+        let parsed = parse("a & b && c | d || e >> 2 < 1").unwrap();
+        let var = |name: &str| Box::new(EVar(String::from(name)));
+        assert_eq!(
+            parsed,
+            EOr(
+                Box::new(EAnd(
+                    Box::new(EBitAnd(var("a"), var("b"))),
+                    Box::new(EBitOr(var("c"), var("d"))),
+                )),
+                Box::new(ELt(
+                    Box::new(EShr(var("e"), Box::new(ENum(Number::from(2))))),
+                    Box::new(ENum(Number::from(1))),
+                )),
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_decimal_numbers() {
+        assert_eq!(parse("3.14").unwrap(), ENum("3.14".parse().unwrap()));
+        assert_eq!(parse(".5").unwrap(), ENum("0.5".parse().unwrap()));
+        assert_eq!(parse("2.").unwrap(), ENum(Number::from(2)));
+        assert_eq!(
+            parse("1.5 * 2").unwrap(),
+            EMul(Box::new(ENum("1.5".parse().unwrap())), Box::new(ENum(Number::from(2))))
+        );
+    }
+
+    #[test]
+    fn test_parse_scientific_notation() {
+        assert_eq!(parse("1e-9").unwrap(), ENum("0.000000001".parse().unwrap()));
+        assert_eq!(
+            parse("6.022E23").unwrap(),
+            ENum("602200000000000000000000".parse().unwrap())
+        );
+        assert_eq!(
+            parse("2e3 - 1").unwrap(),
+            ESub(Box::new(ENum(Number::from(2000))), Box::new(ENum(Number::from(1))))
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_numbers() {
+        assert!(parse("1.2.3").is_err());
+        assert!(parse("1e").is_err());
+        assert!(parse("4e+ 2").is_err());
+        assert!(parse(".").is_err());
+        // Literals too large or too precise to compute with
+        assert!(parse("1e999999999").is_err());
+        assert!(parse("1e-999999999").is_err());
+        assert!(parse("1e99999999999999999999").is_err());
+        assert_eq!(parse("1e-5").unwrap(), ENum("0.00001".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_parse_prefixed_literals() {
+        assert_eq!(parse("0xff").unwrap(), ENum(Number::from(255)));
+        assert_eq!(parse("0x1F").unwrap(), ENum(Number::from(31)));
+        assert_eq!(parse("0o17").unwrap(), ENum(Number::from(15)));
+        assert_eq!(
+            parse("0b101 & 0b1").unwrap(),
+            EBitAnd(Box::new(ENum(Number::from(5))), Box::new(ENum(Number::from(1))))
+        );
+        assert!(parse("0b102").is_err());
+        assert!(parse("0x").is_err());
+    }
+
+    #[test]
+    fn test_parse_literals_in_other_bases() {
+        let parse = |input| super::parse_in_base(input, 16).map(without_spans);
+        assert_eq!(parse("1F").unwrap(), ENum(Number::from(31)));
+        assert_eq!(parse("0A.8").unwrap(), ENum("10.5".parse().unwrap()));
+        assert_eq!(parse("0b11").unwrap(), ENum(Number::from(3)));
+        // As in bc, words made of digits of the base are literals
+        assert_eq!(parse("FF").unwrap(), ENum(Number::from(255)));
+        assert_eq!(parse("F.8 + 1").unwrap(), EAdd(
+            Box::new(ENum("15.5".parse().unwrap())),
+            Box::new(ENum(Number::from(1)))
+        ));
+        assert_eq!(parse("FG").unwrap(), EVar(String::from("FG")));
+        assert_eq!(parse("Fa").unwrap(), EVar(String::from("Fa")));
+        assert_eq!(parse("F_1").unwrap(), EVar(String::from("F_1")));
+        let parse_decimal = |input| super::parse_in_base(input, 10).map(without_spans);
+        assert_eq!(parse_decimal("FF").unwrap(), EVar(String::from("FF")));
+        assert!(parse("1e5").is_err());
+        match super::parse_in_base("x = 1012", 2) {
+            Err(ParseError(error)) => {
+                assert_eq!(error.column, 8);
+                assert_eq!(error.expected, "a digit below ibase");
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_parse_ignores_line_comments() {
+        let parsed = parse("# a comment on its own line
+            1 + 2 # a trailing comment").unwrap();
+        assert_eq!(
+            parsed,
+            EAdd(Box::new(ENum(Number::from(1))), Box::new(ENum(Number::from(2))))
+        );
+    }
+
+    #[test]
+    fn test_parse_ignores_block_comments() {
+        let parsed = parse("/* leading */ 6 /* spanning
+            two lines */ / /**/ 3").unwrap();
+        assert_eq!(
+            parsed,
+            EDiv(Box::new(ENum(Number::from(6))), Box::new(ENum(Number::from(3))))
+        );
+    }
+
+    #[test]
+    fn test_parse_comments_inside_function_definitions() {
+        let function_definiton = "# squares a number
+            define square(n) { # the body
+              /* n * n */ return n * n; # done
+            }";
+        let parsed = parse(function_definiton).unwrap();
+        assert_eq!(
+            parsed,
+            EDefun(
+                String::from("square"),
+                Lambda {
+                    params: vec![String::from("n")],
+                    body: vec![
+                        EReturn(Box::new(EMul(
+                            Box::new(EVar(String::from("n"))),
+                            Box::new(EVar(String::from("n"))),
+                        ))),
+                    ],
+                },
+            )
+        );
     }
 
     #[test]
@@ -212,9 +1280,82 @@ mod tests {
             ELet(
                 String::from("phi"),
                 Box::new(ESub(
-                    Box::new(EAdd(Box::new(ENum(20.0)), Box::new(ENum(30.0)))),
-                    Box::new(ENum(10.0)),
+                    Box::new(EAdd(
+                        Box::new(ENum(Number::from(20))),
+                        Box::new(ENum(Number::from(30))),
+                    )),
+                    Box::new(ENum(Number::from(10))),
+                )),
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_assignments() {
+        assert_eq!(
+            parse("x = y == 2").unwrap(),
+            EAssign(
+                String::from("x"),
+                Box::new(EEq(
+                    Box::new(EVar(String::from("y"))),
+                    Box::new(ENum(Number::from(2))),
+                )),
+            )
+        );
+        assert_eq!(
+            parse("total *= 1 + 2").unwrap(),
+            EAssign(
+                String::from("total"),
+                Box::new(EMul(
+                    Box::new(EVar(String::from("total"))),
+                    Box::new(EAdd(
+                        Box::new(ENum(Number::from(1))),
+                        Box::new(ENum(Number::from(2))),
+                    )),
+                )),
+            )
+        );
+        assert_eq!(
+            parse("x -= 1").unwrap(),
+            EAssign(
+                String::from("x"),
+                Box::new(ESub(Box::new(EVar(String::from("x"))), Box::new(ENum(Number::from(1))))),
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_increments_and_decrements() {
+        assert_eq!(parse("++x").unwrap(), EPreInc(String::from("x")));
+        assert_eq!(parse("x--").unwrap(), EPostDec(String::from("x")));
+        assert_eq!(
+            parse("2 * i++ - --j").unwrap(),
+            ESub(
+                Box::new(EMul(
+                    Box::new(ENum(Number::from(2))),
+                    Box::new(EPostInc(String::from("i"))),
                 )),
+                Box::new(EPreDec(String::from("j"))),
+            )
+        );
+        assert_eq!(
+            parse("for (i = 0; i < 3; i++) { s += i; }").unwrap(),
+            EFor(
+                Some(Box::new(EAssign(String::from("i"), Box::new(ENum(Number::zero()))))),
+                Some(Box::new(ELt(
+                    Box::new(EVar(String::from("i"))),
+                    Box::new(ENum(Number::from(3))),
+                ))),
+                Some(Box::new(EPostInc(String::from("i")))),
+                vec![
+                    EAssign(
+                        String::from("s"),
+                        Box::new(EAdd(
+                            Box::new(EVar(String::from("s"))),
+                            Box::new(EVar(String::from("i"))),
+                        )),
+                    ),
+                ],
             )
         );
     }
@@ -226,17 +1367,64 @@ mod tests {
             parsed,
             ESub(
                 Box::new(EAdd(
-                    Box::new(ENum(20.0)),
+                    Box::new(ENum(Number::from(20))),
                     Box::new(EAdd(
-                        Box::new(ENum(30.0)),
+                        Box::new(ENum(Number::from(30))),
                         Box::new(EVar(String::from("phi"))),
                     )),
                 )),
-                Box::new(ENum(10.0)),
+                Box::new(ENum(Number::from(10))),
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_identifiers_with_digits_underscores_and_uppercase() {
+        assert_eq!(
+            parse("fib_recursive(x1) + _tmp * Psi2").unwrap(),
+            EAdd(
+                Box::new(EFunCall(
+                    String::from("fib_recursive"),
+                    vec![EVar(String::from("x1"))],
+                )),
+                Box::new(EMul(
+                    Box::new(EVar(String::from("_tmp"))),
+                    Box::new(EVar(String::from("Psi2"))),
+                )),
             )
         );
     }
 
+    #[test]
+    fn test_parse_identifiers_starting_with_keywords() {
+        assert_eq!(
+            parse("letter = iffy + returned").unwrap(),
+            EAssign(
+                String::from("letter"),
+                Box::new(EAdd(
+                    Box::new(EVar(String::from("iffy"))),
+                    Box::new(EVar(String::from("returned"))),
+                )),
+            )
+        );
+        assert_eq!(
+            parse("let define_x = for_each").unwrap(),
+            ELet(String::from("define_x"), Box::new(EVar(String::from("for_each"))))
+        );
+        assert_eq!(
+            parse("a xor xorb").unwrap(),
+            EBitXor(Box::new(EVar(String::from("a"))), Box::new(EVar(String::from("xorb"))))
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_keywords_as_names() {
+        assert!(parse("let if = 1").is_err());
+        assert!(parse("define while(n) { return n }").is_err());
+        assert!(parse("let x = return").is_err());
+        assert!(parse("else").is_err());
+    }
+
     #[test]
     fn test_parse_return_statements() {
         let parsed = parse("return n * n").unwrap();
@@ -298,16 +1486,174 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_parse_auto_declarations() {
+        let parsed = parse("define f(n) {\n  auto a, b\n  return n\n}").unwrap();
+        let zero = || Box::new(ENum(Number::zero()));
+        assert_eq!(
+            parsed,
+            EDefun(
+                String::from("f"),
+                Lambda {
+                    params: vec![String::from("n")],
+                    body: vec![
+                        ELet(String::from("a"), zero()),
+                        ELet(String::from("b"), zero()),
+                        EReturn(Box::new(EVar(String::from("n")))),
+                    ],
+                },
+            )
+        );
+        assert!(parse("define f() { auto x }").is_ok());
+        assert!(parse("define f() { x = 1; auto y }").is_err());
+        assert!(parse("auto x").is_err());
+        assert!(parse("define f() { auto }").is_err());
+    }
+
+    #[test]
+    fn test_parse_blocks_with_newline_terminated_statements() {
+        let function_definiton = "define fib(n) {
+              let psi = 1 - phi
+              return (phi ^ n - psi ^ n) / (phi - psi)
+            }";
+        let var = |name: &str| Box::new(EVar(String::from(name)));
+        let parsed = parse(function_definiton).unwrap();
+        assert_eq!(
+            parsed,
+            EDefun(
+                String::from("fib"),
+                Lambda {
+                    params: vec![String::from("n")],
+                    body: vec![
+                        ELet(String::from("psi"), Box::new(ESub(
+                            Box::new(ENum(Number::from(1))),
+                            var("phi"),
+                        ))),
+                        EReturn(Box::new(EDiv(
+                            Box::new(ESub(
+                                Box::new(EExp(var("phi"), var("n"))),
+                                Box::new(EExp(var("psi"), var("n"))),
+                            )),
+                            Box::new(ESub(var("phi"), var("psi"))),
+                        ))),
+                    ],
+                },
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_blocks_mixing_terminators() {
+        let parsed = parse("while (1) { x = 1; y = 2
+              z = 3;
+
+              w = 4 }").unwrap();
+        let assign = |name: &str, value: i64| {
+            EAssign(String::from(name), Box::new(ENum(Number::from(value))))
+        };
+        assert_eq!(
+            parsed,
+            EWhile(
+                Box::new(ENum(Number::from(1))),
+                vec![assign("x", 1), assign("y", 2), assign("z", 3), assign("w", 4)],
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_newline_ends_a_statement_in_a_block() {
+        let parsed = parse("while (1) {
+              x = a
+              -b
+            }").unwrap();
+        assert_eq!(
+            parsed,
+            EWhile(
+                Box::new(ENum(Number::from(1))),
+                vec![
+                    EAssign(String::from("x"), Box::new(EVar(String::from("a")))),
+                    ENeg(Box::new(EVar(String::from("b")))),
+                ],
+            )
+        );
+        let parsed = parse("while (1) {
+              x = a \\
+              -b
+            }").unwrap();
+        assert_eq!(
+            parsed,
+            EWhile(
+                Box::new(ENum(Number::from(1))),
+                vec![
+                    EAssign(
+                        String::from("x"),
+                        Box::new(ESub(
+                            Box::new(EVar(String::from("a"))),
+                            Box::new(EVar(String::from("b"))),
+                        )),
+                    ),
+                ],
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_nested_if_statements_without_semicolons() {
+        let recursive_function = "define fib(n) {
+            if (n == 1) {
+              return 1
+            } else if (n == 2) {
+              return 1
+            }
+            else {
+              return fib(n - 2) + fib(n - 1)
+            }
+          }";
+        let n = || Box::new(EVar(String::from("n")));
+        let num = |value: i64| Box::new(ENum(Number::from(value)));
+        let fib = |arg: Expr| Box::new(EFunCall(String::from("fib"), vec![arg]));
+        let parsed = parse(recursive_function).unwrap();
+        assert_eq!(
+            parsed,
+            EDefun(
+                String::from("fib"),
+                Lambda {
+                    params: vec![String::from("n")],
+                    body: vec![
+                        EIf(
+                            vec![
+                                IfExpr { condition: EEq(n(), num(1)), body: vec![EReturn(num(1))] },
+                                IfExpr { condition: EEq(n(), num(2)), body: vec![EReturn(num(1))] },
+                            ],
+                            vec![EReturn(Box::new(EAdd(
+                                fib(ESub(n(), num(2))),
+                                fib(ESub(n(), num(1))),
+                            )))],
+                        ),
+                    ],
+                },
+            )
+        );
+    }
+
     #[test]
     fn test_parse_function_application() {
         let function_call = "multiply(5, 6)";
         let parsed = parse(function_call).unwrap();
         assert_eq!(
             parsed,
-            EFunCall(String::from("multiply"), vec![ENum(5.0), ENum(6.0)])
+            EFunCall(String::from("multiply"), vec![ENum(Number::from(5)), ENum(Number::from(6))])
         );
     }
 
+    #[test]
+    fn test_parse_function_arguments_are_only_expressions() {
+        assert!(parse("max(;;1, ;2)").is_err());
+        assert!(parse("f(return 1)").is_err());
+        assert!(parse("f(define g() {})").is_err());
+        assert!(parse("f(x = 1)").is_err());
+    }
+
     #[test]
     fn test_parses_simple_if_else_statement() {
         let if_definition = "if (n == 1) {
@@ -321,11 +1667,14 @@ mod tests {
             EIf(
                 vec![
                     IfExpr {
-                        condition: (EVar(String::from("n")), ENum(1.0)),
-                        body: vec![EReturn(Box::new(ENum(1.0)))],
+                        condition: EEq(
+                            Box::new(EVar(String::from("n"))),
+                            Box::new(ENum(Number::from(1))),
+                        ),
+                        body: vec![EReturn(Box::new(ENum(Number::from(1))))],
                     },
                 ],
-                vec![EReturn(Box::new(ENum(2.0)))],
+                vec![EReturn(Box::new(ENum(Number::from(2))))],
             )
         );
     }
@@ -349,20 +1698,29 @@ mod tests {
             EIf(
                 vec![
                     IfExpr {
-                        condition: (EVar(String::from("n")), ENum(1.0)),
-                        body: vec![EReturn(Box::new(ENum(1.0)))],
+                        condition: EEq(
+                            Box::new(EVar(String::from("n"))),
+                            Box::new(ENum(Number::from(1))),
+                        ),
+                        body: vec![EReturn(Box::new(ENum(Number::from(1))))],
                     },
                     IfExpr {
-                        condition: (EVar(String::from("n")), ENum(2.0)),
+                        condition: EEq(
+                            Box::new(EVar(String::from("n"))),
+                            Box::new(ENum(Number::from(2))),
+                        ),
                         body: vec![
-                            ELet(String::from("x"), Box::new(ENum(3.0))),
+                            ELet(String::from("x"), Box::new(ENum(Number::from(3)))),
                             EReturn(Box::new(EVar(String::from("x")))),
                         ],
                     },
                     IfExpr {
-                        condition: (EVar(String::from("n")), ENum(3.0)),
+                        condition: EEq(
+                            Box::new(EVar(String::from("n"))),
+                            Box::new(ENum(Number::from(3))),
+                        ),
                         body: vec![
-                            ELet(String::from("y"), Box::new(ENum(4.0))),
+                            ELet(String::from("y"), Box::new(ENum(Number::from(4)))),
                             EReturn(Box::new(EMul(
                                 Box::new(EVar(String::from("y"))),
                                 Box::new(EVar(String::from("y"))),
@@ -370,11 +1728,77 @@ mod tests {
                         ],
                     },
                 ],
-                vec![EReturn(Box::new(ENum(2.0)))],
+                vec![EReturn(Box::new(ENum(Number::from(2))))],
+            )
+        );
+    }
+
+    #[test]
+    fn test_parses_if_statement_without_else() {
+        let parsed = parse("if (n > 1) { return 1; }").unwrap();
+        assert_eq!(
+            parsed,
+            EIf(
+                vec![
+                    IfExpr {
+                        condition: EGt(
+                            Box::new(EVar(String::from("n"))),
+                            Box::new(ENum(Number::from(1))),
+                        ),
+                        body: vec![EReturn(Box::new(ENum(Number::from(1))))],
+                    },
+                ],
+                vec![],
+            )
+        );
+    }
+
+    #[test]
+    fn test_parses_while_loops() {
+        let parsed = parse("while (i < 10) {
+              let i = i + 1;
+              if (i == 5) { continue; };
+              if (i == 8) { break; };
+            }").unwrap();
+        let i = || Box::new(EVar(String::from("i")));
+        let num = |value: i64| Box::new(ENum(Number::from(value)));
+        assert_eq!(
+            parsed,
+            EWhile(
+                Box::new(ELt(i(), num(10))),
+                vec![
+                    ELet(String::from("i"), Box::new(EAdd(i(), num(1)))),
+                    EIf(
+                        vec![IfExpr { condition: EEq(i(), num(5)), body: vec![EContinue] }],
+                        vec![],
+                    ),
+                    EIf(
+                        vec![IfExpr { condition: EEq(i(), num(8)), body: vec![EBreak] }],
+                        vec![],
+                    ),
+                ],
             )
         );
     }
 
+    #[test]
+    fn test_parses_for_loops() {
+        let parsed = parse("for (let i = 0; i < 3; let i = i + 1) { let s = s + i; }").unwrap();
+        let var = |name: &str| Box::new(EVar(String::from(name)));
+        let num = |value: i64| Box::new(ENum(Number::from(value)));
+        assert_eq!(
+            parsed,
+            EFor(
+                Some(Box::new(ELet(String::from("i"), num(0)))),
+                Some(Box::new(ELt(var("i"), num(3)))),
+                Some(Box::new(ELet(String::from("i"), Box::new(EAdd(var("i"), num(1)))))),
+                vec![ELet(String::from("s"), Box::new(EAdd(var("s"), var("i"))))],
+            )
+        );
+        let parsed = parse("for (;;) { break; }").unwrap();
+        assert_eq!(parsed, EFor(None, None, None, vec![EBreak]));
+    }
+
     #[test]
     fn test_parses_recursive_function_definitions() {
         let recursive_function = "define fibrecursive(n) {
@@ -398,12 +1822,18 @@ mod tests {
                         EIf(
                             vec![
                                 IfExpr {
-                                    condition: (EVar(String::from("n")), ENum(1.0)),
-                                    body: vec![EReturn(Box::new(ENum(1.0)))],
+                                    condition: EEq(
+                                        Box::new(EVar(String::from("n"))),
+                                        Box::new(ENum(Number::from(1))),
+                                    ),
+                                    body: vec![EReturn(Box::new(ENum(Number::from(1))))],
                                 },
                                 IfExpr {
-                                    condition: (EVar(String::from("n")), ENum(2.0)),
-                                    body: vec![EReturn(Box::new(ENum(1.0)))],
+                                    condition: EEq(
+                                        Box::new(EVar(String::from("n"))),
+                                        Box::new(ENum(Number::from(2))),
+                                    ),
+                                    body: vec![EReturn(Box::new(ENum(Number::from(1))))],
                                 },
                             ],
                             vec![
@@ -413,7 +1843,7 @@ mod tests {
                                         vec![
                                             ESub(
                                                 Box::new(EVar(String::from("n"))),
-                                                Box::new(ENum(1.0))
+                                                Box::new(ENum(Number::from(1)))
                                             ),
                                         ],
                                     )),
@@ -428,4 +1858,146 @@ mod tests {
             )
         );
     }
+
+    fn syntax_error_of(input: &str) -> SyntaxError {
+        match parse(input) {
+            Err(ParseError(error)) => error,
+            result => panic!("expected a parse error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_parse_error_points_at_the_missing_operand() {
+        let error = syntax_error_of("1 + * 2");
+        assert_eq!((error.line, error.column), (1, 5));
+        assert_eq!(error.expected, "an expression");
+        assert_eq!(
+            error.to_string(),
+            "1:5: Parse Error: expected an expression\n1 + * 2\n    ^"
+        );
+    }
+
+    #[test]
+    fn test_parse_error_on_trailing_input() {
+        let error = syntax_error_of("1 2");
+        assert_eq!((error.line, error.column), (1, 3));
+        assert_eq!(error.expected, "an operator or the end of the statement");
+        assert_eq!(parse("1;\n").unwrap(), ENum(Number::from(1)));
+    }
+
+    #[test]
+    fn test_parse_error_at_the_end_of_the_input() {
+        let error = syntax_error_of("(1 + 2");
+        assert_eq!((error.line, error.column), (1, 7));
+        assert_eq!(error.expected, "')'");
+        let error = syntax_error_of("while (x < 3) { x++");
+        assert_eq!(error.expected, "'}'");
+        assert_eq!(syntax_error_of("").expected, "a statement");
+    }
+
+    #[test]
+    fn test_parse_error_inside_a_multiline_definition() {
+        let error = syntax_error_of("define f(n) {\n  return n +\n}");
+        assert_eq!((error.line, error.column), (2, 13));
+        assert_eq!(error.source_line, "  return n +");
+        let error = syntax_error_of("define f(n) {\n  1 2\n}");
+        assert_eq!((error.line, error.column), (2, 5));
+        assert_eq!(error.expected, "';', a newline or '}'");
+    }
+
+    #[test]
+    fn test_parse_error_describes_the_expected_token() {
+        assert_eq!(syntax_error_of("let = 3").expected, "a variable name");
+        assert_eq!(syntax_error_of("let x 3").expected, "'='");
+        assert_eq!(syntax_error_of("if x) { 1 }").expected, "'('");
+        assert_eq!(syntax_error_of("for (i = 0 i < 3; i++) {}").expected, "';'");
+        assert_eq!(syntax_error_of("f(1, 2").expected, "an argument or ')'");
+    }
+
+    #[test]
+    fn test_parse_error_caret_keeps_tabs() {
+        let error = syntax_error_of("\t\tx = 1 +* 2");
+        assert_eq!(error.column, 10);
+        assert!(error.to_string().ends_with("\t\tx = 1 +* 2\n\t\t       ^"));
+    }
+
+    #[test]
+    fn test_parse_records_positions() {
+        let span = |line, column| Span { line, column };
+        let parsed = super::parse("define f(n) {\n  return n / 0\n}").unwrap();
+        let body = ELocated(
+            span(2, 3),
+            Box::new(EReturn(Box::new(ELocated(
+                span(2, 12),
+                Box::new(EDiv(
+                    Box::new(ELocated(span(2, 10), Box::new(EVar(String::from("n"))))),
+                    Box::new(ENum(Number::from(0))),
+                )),
+            )))),
+        );
+        assert_eq!(
+            parsed,
+            ELocated(
+                span(1, 1),
+                Box::new(EDefun(
+                    String::from("f"),
+                    Lambda { params: vec![String::from("n")], body: vec![body] },
+                )),
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_statements_after_the_first_line() {
+        let input = "# skipped\n\n  foo(1)\nbar";
+        match statement(input, 0, 10, 10) {
+            Done(rest, ELocated(span, _)) => {
+                assert_eq!(span, Span { line: 12, column: 3 });
+                assert_eq!(rest, "\nbar");
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_statements_end_at_a_newline_or_semicolon() {
+        match statement("1; 2", 0, 1, 10) {
+            Done(rest, _) => assert_eq!(rest, "; 2"),
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert!(statement("1 2", 0, 1, 10).is_err());
+        match syntax_error("x\n1 2", 5) {
+            ParseError(error) => assert_eq!((error.line, error.column), (6, 3)),
+            error => panic!("unexpected error {:?}", error),
+        }
+        assert!(is_blank(" ;\n# nothing here\n/* or here */;"));
+        assert!(!is_blank("\n 1"));
+    }
+
+    #[test]
+    fn test_skip_statement_resumes_after_the_statement() {
+        assert_eq!(skip_statement("\n1 + * 2\nx"), "x");
+        assert_eq!(skip_statement("1 + ; 2"), " 2");
+        assert_eq!(skip_statement("define f() {\n  1 +\n}\nnext"), "next");
+        assert_eq!(skip_statement("1 # {\n2"), "2");
+        assert_eq!(skip_statement("1 /* ; { */ 2;3"), "3");
+        assert_eq!(skip_statement("1 + \\\n 2\n3"), "3");
+        assert_eq!(skip_statement("{ never closed\n"), "");
+    }
+
+    #[test]
+    fn test_is_incomplete_until_brackets_and_comments_close() {
+        assert!(is_incomplete("define f(n) {\n"));
+        assert!(is_incomplete("define f(n) {\n  if (n > 1) {\n    return n\n  }\n"));
+        assert!(!is_incomplete("define f(n) {\n  return n\n}\n"));
+        assert!(!is_incomplete("max(1,\n"));
+        assert!(!is_incomplete("(1 +\n"));
+        assert!(is_incomplete("1 + \\\n"));
+        assert!(is_incomplete("1 /* still\n"));
+        assert!(is_incomplete("1 +"));
+        assert!(!is_incomplete("1 # {\n"));
+        assert!(!is_incomplete("1 + * 2\n"));
+        assert!(!is_incomplete("1 }\n"));
+        assert!(!is_incomplete("\n"));
+    }
 }
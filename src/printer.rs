@@ -0,0 +1,294 @@
+use std::fmt;
+use types::{EnvValue, Environment, Expr, IfExpr, Lambda};
+use types::Expr::*;
+
+// How tightly each kind of expression binds, following the layers of the
+// grammar in the parser, so that parentheses are only added where the parser
+// needs them
+const OR: u8 = 1;
+const AND: u8 = 2;
+const RELATION: u8 = 3;
+const BIT_OR: u8 = 4;
+const BIT_XOR: u8 = 5;
+const BIT_AND: u8 = 6;
+const SHIFT: u8 = 7;
+const SUM: u8 = 8;
+const PRODUCT: u8 = 9;
+const UNARY: u8 = 10;
+const POWER: u8 = 11;
+const ATOM: u8 = 12;
+
+const INDENT: &str = "    ";
+
+/// Writes expressions back out as source text that parses to the same
+/// expression. Blocks are laid out one statement per line, indented by four
+/// spaces.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_statement(f, self, 0)
+    }
+}
+
+/// The source of a function definition, as in `define f(n) { ... }`.
+pub fn definition(name: &str, lambda: &Lambda) -> String {
+    EDefun(name.to_string(), lambda.clone()).to_string()
+}
+
+/// The source of a block of statements, braces included.
+pub fn block(body: &[Expr]) -> String {
+    struct Block<'a>(&'a [Expr]);
+    impl<'a> fmt::Display for Block<'a> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write_block(f, self.0, 0)
+        }
+    }
+    Block(body).to_string()
+}
+
+/// The source of a `.bc` file that recreates the global variables and
+/// functions of `env`. Native functions are left out, since they are always
+/// defined, and so are infinities and NaN outside IEEE mode, where they would
+/// fail to load as divisions by zero.
+pub fn session(env: &Environment) -> String {
+    let mut source = String::new();
+    for (name, value) in env.globals() {
+        if let EnvValue::ComputedResult(ref value) = *value {
+            if !value.is_finite() && !env.ieee() {
+                continue;
+            }
+            source.push_str(&format!("{} = {}\n", name, ENum(value.clone())));
+        }
+    }
+    for (name, value) in env.globals() {
+        if let EnvValue::LambdaRef(ref lambda) = *value {
+            source.push_str(&format!("{}\n", definition(name, lambda)));
+        }
+    }
+    source
+}
+
+fn write_statement(f: &mut fmt::Formatter, expr: &Expr, indent: usize) -> fmt::Result {
+    match *expr {
+        ELocated(_, ref expr) => write_statement(f, expr, indent),
+        ELet(ref name, ref value) => write!(f, "let {} = {}", name, value),
+        EAssign(ref name, ref value) => write!(f, "{} = {}", name, value),
+        EReturn(ref value) => write!(f, "return {}", value),
+        EBreak => write!(f, "break"),
+        EContinue => write!(f, "continue"),
+        EDefun(ref name, ref lambda) => {
+            write!(f, "define {}({}) ", name, lambda.params.join(", "))?;
+            write_block(f, &lambda.body, indent)
+        }
+        EIf(ref ifs, ref otherwise) => write_if(f, ifs, otherwise, indent),
+        EWhile(ref condition, ref body) => {
+            write!(f, "while ({}) ", condition)?;
+            write_block(f, body, indent)
+        }
+        EFor(ref init, ref condition, ref step, ref body) => {
+            write!(f, "for (")?;
+            write_clause(f, init, indent)?;
+            write!(f, "; ")?;
+            write_clause(f, condition, indent)?;
+            write!(f, "; ")?;
+            write_clause(f, step, indent)?;
+            write!(f, ") ")?;
+            write_block(f, body, indent)
+        }
+        ref expr => write_operand(f, expr, 0),
+    }
+}
+
+fn write_clause(
+    f: &mut fmt::Formatter,
+    clause: &Option<Box<Expr>>,
+    indent: usize,
+) -> fmt::Result {
+    match *clause {
+        Some(ref clause) => write_statement(f, clause, indent),
+        None => Ok(()),
+    }
+}
+
+fn write_if(
+    f: &mut fmt::Formatter,
+    ifs: &[IfExpr],
+    otherwise: &[Expr],
+    indent: usize,
+) -> fmt::Result {
+    for (idx, branch) in ifs.iter().enumerate() {
+        if idx > 0 {
+            write!(f, " else ")?;
+        }
+        write!(f, "if ({}) ", branch.condition)?;
+        write_block(f, &branch.body, indent)?;
+    }
+    if !otherwise.is_empty() {
+        write!(f, " else ")?;
+        write_block(f, otherwise, indent)?;
+    }
+    Ok(())
+}
+
+fn write_block(f: &mut fmt::Formatter, body: &[Expr], indent: usize) -> fmt::Result {
+    if body.is_empty() {
+        return write!(f, "{{}}");
+    }
+    writeln!(f, "{{")?;
+    for expr in body {
+        write!(f, "{}", INDENT.repeat(indent + 1))?;
+        write_statement(f, expr, indent + 1)?;
+        writeln!(f)?;
+    }
+    write!(f, "{}}}", INDENT.repeat(indent))
+}
+
+// Writes `expr`, in parentheses if it binds less tightly than `precedence`
+fn write_operand(f: &mut fmt::Formatter, expr: &Expr, precedence: u8) -> fmt::Result {
+    if self::precedence(expr) < precedence {
+        write!(f, "(")?;
+        write_operand(f, expr, 0)?;
+        return write!(f, ")");
+    }
+    if let Some((op, precedence, lhs, rhs)) = binary(expr) {
+        // `^` is right-associative and only takes a number, variable or call
+        // on its left; everything else is left-associative
+        let (lhs_precedence, rhs_precedence) = if op == "^" {
+            (ATOM, UNARY)
+        } else {
+            (precedence, precedence + 1)
+        };
+        write_operand(f, lhs, lhs_precedence)?;
+        write!(f, " {} ", op)?;
+        return write_operand(f, rhs, rhs_precedence);
+    }
+    match *expr {
+        ELocated(_, ref expr) => write_operand(f, expr, precedence),
+        ENum(ref value) if !value.is_finite() => {
+            // Infinities and NaN have no literal, but IEEE mode computes them
+            let numerator = if value.to_f64().is_nan() {
+                "0"
+            } else if value.is_negative() {
+                "-1"
+            } else {
+                "1"
+            };
+            write!(f, "{} / 0", numerator)
+        }
+        ENum(ref value) => write!(f, "{}", value),
+        EVar(ref name) => write!(f, "{}", name),
+        ENeg(ref expr) => {
+            write!(f, "-")?;
+            write_operand(f, expr, UNARY)
+        }
+        ENot(ref expr) => {
+            write!(f, "!")?;
+            write_operand(f, expr, UNARY)
+        }
+        EPreInc(ref name) => write!(f, "++{}", name),
+        EPreDec(ref name) => write!(f, "--{}", name),
+        EPostInc(ref name) => write!(f, "{}++", name),
+        EPostDec(ref name) => write!(f, "{}--", name),
+        EFunCall(ref name, ref args) => {
+            write!(f, "{}(", name)?;
+            for (idx, arg) in args.iter().enumerate() {
+                if idx > 0 {
+                    write!(f, ", ")?;
+                }
+                write_operand(f, arg, 0)?;
+            }
+            write!(f, ")")
+        }
+        ref statement => write_statement(f, statement, 0),
+    }
+}
+
+fn precedence(expr: &Expr) -> u8 {
+    match *expr {
+        ELocated(_, ref expr) => precedence(expr),
+        ENum(ref value) if !value.is_finite() => PRODUCT,
+        ENum(ref value) if value.is_negative() => UNARY,
+        ENeg(_) | ENot(_) | EPreInc(_) | EPreDec(_) => UNARY,
+        ENum(_) | EVar(_) | EPostInc(_) | EPostDec(_) | EFunCall(..) => ATOM,
+        ref expr => binary(expr).map_or(ATOM, |(_, precedence, _, _)| precedence),
+    }
+}
+
+fn binary(expr: &Expr) -> Option<(&'static str, u8, &Expr, &Expr)> {
+    let (op, precedence, lhs, rhs) = match *expr {
+        EOr(ref lhs, ref rhs) => ("||", OR, lhs, rhs),
+        EAnd(ref lhs, ref rhs) => ("&&", AND, lhs, rhs),
+        EEq(ref lhs, ref rhs) => ("==", RELATION, lhs, rhs),
+        ENe(ref lhs, ref rhs) => ("!=", RELATION, lhs, rhs),
+        ELt(ref lhs, ref rhs) => ("<", RELATION, lhs, rhs),
+        ELe(ref lhs, ref rhs) => ("<=", RELATION, lhs, rhs),
+        EGt(ref lhs, ref rhs) => (">", RELATION, lhs, rhs),
+        EGe(ref lhs, ref rhs) => (">=", RELATION, lhs, rhs),
+        EBitOr(ref lhs, ref rhs) => ("|", BIT_OR, lhs, rhs),
+        EBitXor(ref lhs, ref rhs) => ("xor", BIT_XOR, lhs, rhs),
+        EBitAnd(ref lhs, ref rhs) => ("&", BIT_AND, lhs, rhs),
+        EShl(ref lhs, ref rhs) => ("<<", SHIFT, lhs, rhs),
+        EShr(ref lhs, ref rhs) => (">>", SHIFT, lhs, rhs),
+        EAdd(ref lhs, ref rhs) => ("+", SUM, lhs, rhs),
+        ESub(ref lhs, ref rhs) => ("-", SUM, lhs, rhs),
+        EMul(ref lhs, ref rhs) => ("*", PRODUCT, lhs, rhs),
+        EDiv(ref lhs, ref rhs) => ("/", PRODUCT, lhs, rhs),
+        EIntDiv(ref lhs, ref rhs) => ("//", PRODUCT, lhs, rhs),
+        EMod(ref lhs, ref rhs) => ("%", PRODUCT, lhs, rhs),
+        EExp(ref lhs, ref rhs) => ("^", POWER, lhs, rhs),
+        _ => return None,
+    };
+    Some((op, precedence, lhs, rhs))
+}
+
+#[cfg(test)]
+mod tests {
+    use parser::parse;
+
+    fn reprint(source: &str) -> String {
+        parse(source).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_prints_operators_with_only_the_parentheses_needed() {
+        assert_eq!(reprint("1+2*3"), "1 + 2 * 3");
+        assert_eq!(reprint("(1+2)*3"), "(1 + 2) * 3");
+        assert_eq!(reprint("1-(2-3)"), "1 - (2 - 3)");
+        assert_eq!(reprint("(1-2)-3"), "1 - 2 - 3");
+        assert_eq!(reprint("2^3^2"), "2 ^ 3 ^ 2");
+        assert_eq!(reprint("(2^3)^2"), "(2 ^ 3) ^ 2");
+        assert_eq!(reprint("(-2)^2"), "(-2) ^ 2");
+        assert_eq!(reprint("-2^2"), "-2 ^ 2");
+        assert_eq!(reprint("2^-x++"), "2 ^ -x++");
+        assert_eq!(reprint("!(a && b) || c xor 1 << 2"), "!(a && b) || c xor 1 << 2");
+        assert_eq!(reprint("f(1, g(x)) // 2 % 3"), "f(1, g(x)) // 2 % 3");
+    }
+
+    #[test]
+    fn test_prints_statements_and_blocks() {
+        assert_eq!(reprint("let x = 1.50"), "let x = 1.50");
+        assert_eq!(reprint("x += 2"), "x = x + 2");
+        assert_eq!(
+            reprint("define f(a,b) { if (a > b) { return a } else { return b } }"),
+            "define f(a, b) {\n    if (a > b) {\n        return a\n    } else {\n        return b\n    }\n}"
+        );
+        assert_eq!(
+            reprint("for (i = 0; i < 3; i++) { if (i == 1) { continue } }"),
+            "for (i = 0; i < 3; i++) {\n    if (i == 1) {\n        continue\n    }\n}"
+        );
+        assert_eq!(reprint("while (1) {}"), "while (1) {}");
+    }
+
+    #[test]
+    fn test_printed_statements_parse_back_to_the_same_source() {
+        let sources = [
+            "define fib(n) { if (n < 2) { return n } else if (n == 2) { return 1 }\n\
+             return fib(n - 1) + fib(n - 2) }",
+            "for (;;) { break }",
+            "x = -(1 - 2) * (3 / -4) ^ 2",
+        ];
+        for source in sources.iter() {
+            let printed = reprint(source);
+            assert_eq!(reprint(&printed), printed);
+        }
+    }
+}
@@ -1,21 +1,458 @@
-use std::io::{self, Write};
-use parser::parse;
-use types::Environment;
-use types::display;
+use std::env;
+use std::io::{self, BufRead, IsTerminal, Stdin};
+use std::path::PathBuf;
+use std::time::Instant;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use parser::{is_blank, is_incomplete, KEYWORDS};
+use types::{EnvValue, Environment};
+use types::{display, display_in};
 use evaluator::*;
+use filereader::StatementReader;
+use printer;
+use session;
 
-pub fn init() {
-    let mut environment = Environment::new();
+// Runs the statements of `input` one after the other, the way a file is run,
+// and returns their results, one per line
+fn run_statements(environment: &mut Environment, input: &str) -> String {
+    let mut reader = StatementReader::new(input.as_bytes());
+    let mut results = Vec::new();
+    reader.set_ibase(environment.ibase());
+    while let Some(statement) = reader.next() {
+        let quiet = statement.as_ref().is_ok_and(|expr| is_quiet(environment, expr));
+        let result = statement.and_then(|expr| evaluate(environment, expr));
+        if !quiet || result.is_err() {
+            results.push(display(result, environment.obase()));
+        }
+        reader.set_ibase(environment.ibase());
+    }
+    results.join("\n")
+}
+
+const PROMPT: &str = "> ";
+/// Shown instead of `PROMPT` while a statement spans several lines.
+const CONTINUATION_PROMPT: &str = "... ";
+
+pub fn init(mut environment: Environment) {
+    let mut lines = Lines::new(&environment);
+    let mut input = String::new();
     loop {
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
-        let result = format!(
-            "{}\n",
-            display(parse(&input[..]).and_then(
-                |expr| evaluate(&mut environment, expr),
-            ))
+        let prompt = if input.is_empty() { PROMPT } else { CONTINUATION_PROMPT };
+        let eof = match lines.read(prompt) {
+            Input::Line(line) => {
+                input.push_str(&line);
+                false
+            }
+            // Ctrl+C abandons the statement being typed, like in a shell
+            Input::Interrupted => {
+                input.clear();
+                continue;
+            }
+            Input::End => true,
+        };
+        // Commands always fit on a single line
+        let is_command = input.trim_start().starts_with(':');
+        if !eof && !is_command && is_incomplete(&input) {
+            continue;
+        }
+        if is_command {
+            lines.remember(&input);
+            let output = command(&mut environment, input.trim());
+            if !output.is_empty() {
+                println!("{}", output);
+            }
+            lines.update_names(&environment);
+        } else if !is_blank(&input) {
+            lines.remember(&input);
+            let output = run_statements(&mut environment, &input);
+            if !output.is_empty() {
+                println!("{}", output);
+            }
+            lines.update_names(&environment);
+        }
+        input.clear();
+        if eof {
+            lines.finish();
+            break;
+        }
+    }
+}
+
+const HELP: &str = "\
+:vars             list the variables and their values
+:funcs            list the functions and their parameters
+:show NAME        print the definition of a function or the value of a variable
+:undef NAME       forget a variable or function
+:reset            forget everything that has been defined
+:load FILE        run the statements in FILE, or restore a session saved as .json
+:save FILE        write the variables and functions to FILE, as JSON if it ends
+                  in .json and as bc source otherwise
+:time STATEMENT   run STATEMENT and report how long it took
+:help             show this help";
+
+const COMMANDS: &[&str] = &[
+    ":vars", ":funcs", ":show", ":undef", ":reset", ":load", ":save", ":time", ":help",
+];
+
+/// Runs a REPL command such as `:vars` or `:load file.bc`, returning what it
+/// prints.
+fn command(environment: &mut Environment, line: &str) -> String {
+    let (name, argument) = match line.find(char::is_whitespace) {
+        Some(idx) => (&line[..idx], line[idx..].trim()),
+        None => (line, ""),
+    };
+    match (name, argument) {
+        (":vars", "") => {
+            let obase = environment.obase();
+            let vars: Vec<String> = environment
+                .globals()
+                .into_iter()
+                .filter_map(|(name, value)| match *value {
+                    EnvValue::ComputedResult(ref value) => {
+                        Some(format!("{} = {}", name, value.to_string_radix(obase)))
+                    }
+                    _ => None,
+                })
+                .collect();
+            vars.join("\n")
+        }
+        (":funcs", "") => {
+            // There are many more built-in functions than user-defined ones,
+            // so they are listed by name only, after the others
+            let mut funcs = Vec::new();
+            let mut natives = Vec::new();
+            for (name, value) in environment.globals() {
+                match *value {
+                    EnvValue::LambdaRef(ref lambda) => {
+                        funcs.push(format!("{}({})", name, lambda.params.join(", ")))
+                    }
+                    EnvValue::NativeFn(_) => natives.push(name),
+                    EnvValue::ComputedResult(_) => (),
+                }
+            }
+            if !natives.is_empty() {
+                funcs.push(format!("native: {}", natives.join(", ")));
+            }
+            funcs.join("\n")
+        }
+        (":show", name) if !name.is_empty() => match environment.get(name.to_string()) {
+            Some(EnvValue::ComputedResult(value)) => {
+                format!("{} = {}", name, value.to_string_radix(environment.obase()))
+            }
+            Some(EnvValue::LambdaRef(lambda)) => printer::definition(name, &lambda),
+            Some(EnvValue::NativeFn(f)) => {
+                format!("{} is a native function taking {}", name, f.arity)
+            }
+            None => format!("{} isn't defined", name),
+        },
+        (":undef", name) if !name.is_empty() => match environment.remove(name) {
+            Some(_) => String::new(),
+            None => format!("{} isn't defined", name),
+        },
+        (":reset", "") => {
+            let mut fresh = Environment::new();
+            fresh
+                .set_scale(environment.scale())
+                .set_ieee(environment.ieee())
+                .set_bc_mathlib(environment.bc_mathlib())
+                .set_ibase(environment.ibase())
+                .set_obase(environment.obase());
+            *environment = fresh;
+            String::new()
+        }
+        (":load", file_name) if file_name.ends_with(".json") => {
+            match session::load(environment, file_name) {
+                Ok(()) => String::new(),
+                Err(error) => display_in(file_name, Err(error), environment.obase()),
+            }
+        }
+        (":load", file_name) if !file_name.is_empty() => {
+            match StatementReader::open(file_name) {
+                Ok(reader) => {
+                    let mut results = Vec::new();
+                    session::run_source(environment, reader, |env, result| {
+                        results.push(display_in(file_name, result, env.obase()));
+                        true
+                    });
+                    results.join("\n")
+                }
+                Err(error) => format!("Couldn't open {}: {}", file_name, error),
+            }
+        }
+        (":save", file_name) if !file_name.is_empty() => {
+            match session::save(environment, file_name) {
+                Ok(()) => String::new(),
+                Err(error) => format!("Couldn't write {}: {}", file_name, error),
+            }
+        }
+        (":time", statement) if !statement.is_empty() => {
+            let start = Instant::now();
+            let output = run_statements(environment, statement);
+            let elapsed = start.elapsed();
+            format!("{}\ntime: {:?}", output, elapsed)
+        }
+        (":help", "") => HELP.to_string(),
+        _ if COMMANDS.contains(&name) => {
+            let usage = HELP.lines().find(|usage| usage.starts_with(name)).unwrap_or(name);
+            format!("Usage: {}", usage)
+        }
+        _ => format!("Unknown command {}, see :help", name),
+    }
+}
+
+/// Where the REPL reads its input from: a line editor with history and
+/// completion when someone is typing at a terminal, or plain standard input
+/// when a script is piped in.
+enum Input {
+    /// A line, including its newline.
+    Line(String),
+    /// The user pressed Ctrl+C.
+    Interrupted,
+    End,
+}
+
+enum Lines {
+    Editor(Box<Editor<Names, DefaultHistory>>, Option<PathBuf>),
+    Piped(Stdin),
+}
+
+impl Lines {
+    fn new(environment: &Environment) -> Lines {
+        let stdin = io::stdin();
+        if !stdin.is_terminal() {
+            return Lines::Piped(stdin);
+        }
+        let mut editor = match Editor::new() {
+            Ok(editor) => editor,
+            Err(error) => {
+                eprintln!("Couldn't start the line editor: {}", error);
+                return Lines::Piped(stdin);
+            }
+        };
+        editor.set_helper(Some(Names(environment.names())));
+        let history = history_file();
+        if let Some(ref path) = history {
+            // There's no history yet the first time the REPL runs
+            let _ = editor.load_history(path);
+        }
+        Lines::Editor(Box::new(editor), history)
+    }
+
+    /// Reads the next line.
+    fn read(&mut self, prompt: &str) -> Input {
+        match *self {
+            Lines::Editor(ref mut editor, _) => match editor.readline(prompt) {
+                Ok(line) => Input::Line(line + "\n"),
+                Err(ReadlineError::Interrupted) => Input::Interrupted,
+                Err(ReadlineError::Eof) => Input::End,
+                Err(error) => {
+                    eprintln!("Couldn't read the input: {}", error);
+                    Input::End
+                }
+            },
+            Lines::Piped(ref stdin) => {
+                let mut line = Vec::new();
+                match stdin.lock().read_until(b'\n', &mut line) {
+                    Ok(0) => Input::End,
+                    Ok(_) => Input::Line(String::from_utf8_lossy(&line).into_owned()),
+                    Err(error) => {
+                        eprintln!("Couldn't read the input: {}", error);
+                        Input::End
+                    }
+                }
+            }
+        }
+    }
+
+    /// Adds a complete statement to the history, so that a multi-line
+    /// definition comes back as a whole.
+    fn remember(&mut self, statement: &str) {
+        if let Lines::Editor(ref mut editor, _) = *self {
+            let _ = editor.add_history_entry(statement.trim_end());
+        }
+    }
+
+    fn update_names(&mut self, environment: &Environment) {
+        if let Lines::Editor(ref mut editor, _) = *self {
+            if let Some(names) = editor.helper_mut() {
+                names.0 = environment.names();
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        if let Lines::Editor(ref mut editor, ref history) = *self {
+            println!();
+            if let Some(ref path) = *history {
+                if let Err(error) = editor.save_history(path) {
+                    eprintln!("Couldn't save the history to {}: {}", path.display(), error);
+                }
+            }
+        }
+    }
+}
+
+/// `$XDG_DATA_HOME/bcalc_history`, or `~/.bcalc_history` when
+/// `XDG_DATA_HOME` isn't set.
+fn history_file() -> Option<PathBuf> {
+    match env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => Some(PathBuf::from(dir).join("bcalc_history")),
+        None => env::var_os("HOME").map(|home| PathBuf::from(home).join(".bcalc_history")),
+    }
+}
+
+/// Completes the variables and functions defined in the environment, and the
+/// keywords.
+struct Names(Vec<String>);
+
+impl Completer for Names {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        if line.starts_with(':') && !line[..pos].contains(char::is_whitespace) {
+            let commands = COMMANDS
+                .iter()
+                .filter(|command| command.starts_with(&line[..pos]))
+                .map(|command| command.to_string())
+                .collect();
+            return Ok((0, commands));
+        }
+        Ok(complete_name(&self.0, line, pos))
+    }
+}
+
+impl Hinter for Names {
+    type Hint = String;
+}
+impl Highlighter for Names {}
+impl Validator for Names {}
+impl Helper for Names {}
+
+// Returns where the identifier ending at `pos` starts, and the names and
+// keywords it could be the beginning of
+fn complete_name(names: &[String], line: &str, pos: usize) -> (usize, Vec<String>) {
+    let start = line[..pos]
+        .rfind(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+        .map_or(0, |idx| idx + 1);
+    let prefix = &line[start..pos];
+    if prefix.is_empty() || prefix.starts_with(|c: char| c.is_ascii_digit()) {
+        return (pos, Vec::new());
+    }
+    let mut candidates: Vec<String> = names
+        .iter()
+        .map(String::as_str)
+        .chain(KEYWORDS.iter().cloned())
+        .filter(|name| name.starts_with(prefix))
+        .map(String::from)
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    (start, candidates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::parse;
+
+    #[test]
+    fn test_complete_name_from_environment_and_keywords() {
+        let names = vec![String::from("fib"), String::from("foo_2"), String::from("sqrt")];
+        assert_eq!(
+            complete_name(&names, "1 + f", 5),
+            (4, vec![String::from("fib"), String::from("foo_2"), String::from("for")])
+        );
+        assert_eq!(complete_name(&names, "sq(2)", 2), (0, vec![String::from("sqrt")]));
+        assert_eq!(complete_name(&names, "de", 2), (0, vec![String::from("define")]));
+        assert_eq!(complete_name(&names, "1 + ", 4), (4, Vec::new()));
+        assert_eq!(complete_name(&names, "12", 2), (2, Vec::new()));
+    }
+
+    fn run(environment: &mut Environment, statement: &str) {
+        parse(statement).and_then(|expr| evaluate(environment, expr)).unwrap();
+    }
+
+    #[test]
+    fn test_commands_list_and_show_definitions() {
+        let mut environment = Environment::new();
+        run(&mut environment, "let x = 2.5");
+        run(&mut environment, "define twice(n) { return 2 * n }");
+        assert_eq!(command(&mut environment, ":vars"), "x = 2.5");
+        assert!(command(&mut environment, ":funcs").starts_with("twice(n)\nnative: abs, acos, "));
+        assert_eq!(
+            command(&mut environment, ":show max"),
+            "max is a native function taking at least 1 argument"
+        );
+        assert_eq!(
+            command(&mut environment, ":show twice"),
+            "define twice(n) {\n    return 2 * n\n}"
+        );
+        assert_eq!(command(&mut environment, ":show x"), "x = 2.5");
+        assert_eq!(command(&mut environment, ":show y"), "y isn't defined");
+        assert_eq!(command(&mut environment, ":show"), "Usage: :show NAME        print \
+             the definition of a function or the value of a variable");
+        assert_eq!(command(&mut environment, ":nope"), "Unknown command :nope, see :help");
+        environment.set_obase(16);
+        run(&mut environment, "let y = 255");
+        assert_eq!(command(&mut environment, ":show y"), "y = FF");
+        assert_eq!(command(&mut environment, ":vars"), "x = 2.8\ny = FF");
+    }
+
+    #[test]
+    fn test_commands_forget_definitions() {
+        let mut environment = Environment::new();
+        environment.set_scale(5);
+        run(&mut environment, "let x = 1");
+        run(&mut environment, "let y = 2");
+        assert_eq!(command(&mut environment, ":undef x"), "");
+        assert_eq!(command(&mut environment, ":undef x"), "x isn't defined");
+        assert_eq!(command(&mut environment, ":vars"), "y = 2");
+        assert_eq!(command(&mut environment, ":reset"), "");
+        assert_eq!(command(&mut environment, ":vars"), "");
+        assert_eq!(environment.names(), Environment::new().names());
+        assert_eq!(environment.scale(), 5);
+    }
+
+    #[test]
+    fn test_commands_time_statements() {
+        let mut environment = Environment::new();
+        let output = command(&mut environment, ":time 6 * 7");
+        assert!(output.starts_with("42\ntime: "));
+        let output = command(&mut environment, ":time x = 5; x + 1");
+        assert!(output.starts_with("5\n6\ntime: "));
+    }
+
+    #[test]
+    fn test_run_statements_quietly_when_emulating_bc() {
+        let mut environment = Environment::new();
+        environment.set_bc_mathlib(true);
+        let source = "define f(x) {\n  auto y\n  y = x * 2\n  return y\n}\n\
+                      scale = 2; z = f(3); z + 1\n";
+        assert_eq!(run_statements(&mut environment, source), "7");
+        assert_eq!(
+            run_statements(&mut environment, "scale = -1\n"),
+            "1:1: Runtime Error: scale can't be set to -1"
+        );
+    }
+
+    #[test]
+    fn test_run_statements_like_a_file() {
+        let mut environment = Environment::new();
+        assert_eq!(run_statements(&mut environment, "x = 5; x + 1\n"), "5\n6");
+        assert_eq!(
+            run_statements(&mut environment, "ibase = 16; 1F; y +* 2; 0x10\n"),
+            "16\n31\n1:20: Parse Error: expected an expression\n\
+             ibase = 16; 1F; y +* 2; 0x10\n                   ^\n16"
         );
-        io::stdout().write(result.to_string().as_bytes()).unwrap();
-        io::stdout().flush().unwrap();
     }
 }
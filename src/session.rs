@@ -0,0 +1,254 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use number::Number;
+use parser::parse;
+use printer;
+use evaluator::evaluate;
+use filereader::StatementReader;
+use types::{EnvValue, Environment, Error};
+use types::Error::*;
+
+/// The global variables and functions of an environment, in the structured
+/// form sessions are saved in as JSON.
+///
+/// Variables are kept as decimal text, so that no digits are lost, and
+/// function bodies as source text, so that the file stays readable and keeps
+/// working when the syntax tree changes.
+#[derive(Debug, PartialEq, Default, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(default)]
+    pub variables: BTreeMap<String, String>,
+    #[serde(default)]
+    pub functions: BTreeMap<String, Function>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Function {
+    pub params: Vec<String>,
+    /// The body of the function, braces included.
+    pub body: String,
+}
+
+impl Session {
+    /// Takes the user-defined globals of `env`. Native functions are left
+    /// out, since they are always defined.
+    pub fn of(env: &Environment) -> Session {
+        let mut session = Session::default();
+        for (name, value) in env.globals() {
+            match *value {
+                EnvValue::ComputedResult(ref value) => {
+                    session.variables.insert(name.to_string(), value.to_string());
+                }
+                EnvValue::LambdaRef(ref lambda) => {
+                    let function = Function {
+                        params: lambda.params.clone(),
+                        body: printer::block(&lambda.body),
+                    };
+                    session.functions.insert(name.to_string(), function);
+                }
+                EnvValue::NativeFn(_) => (),
+            }
+        }
+        session
+    }
+
+    /// Defines the variables and functions of the session in `env`,
+    /// replacing any globals of the same name.
+    pub fn restore(self, env: &mut Environment) -> Result<(), Error> {
+        for (name, value) in self.variables {
+            match parse_value(&value) {
+                Some(value) => {
+                    env.assign(name, EnvValue::ComputedResult(value));
+                }
+                None => {
+                    return Err(ReadError(format!("{} isn't a number: {}", name, value)));
+                }
+            }
+        }
+        for (name, function) in self.functions {
+            let params = function.params.join(", ");
+            let source = format!("define {}({}) {}", name, params, function.body);
+            evaluate(env, parse(&source)?)?;
+        }
+        Ok(())
+    }
+}
+
+// Sessions are stored as JSON in `.json` files, and as bc source otherwise
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "json")
+}
+
+/// Writes the user-defined globals of `env` to `path`.
+pub fn save<P: AsRef<Path>>(env: &Environment, path: P) -> io::Result<()> {
+    let path = path.as_ref();
+    let contents = if is_json(path) {
+        let json = serde_json::to_string_pretty(&Session::of(env)).map_err(io::Error::from)?;
+        json + "\n"
+    } else {
+        printer::session(env)
+    };
+    fs::write(path, contents)
+}
+
+/// Runs the statements read by `reader` in `env`, handing each result to
+/// `report` until it returns false. Literals are read in the base `ibase` is
+/// set to when their statement is reached.
+pub fn run_source<R, F>(env: &mut Environment, mut reader: StatementReader<R>, mut report: F)
+where
+    R: BufRead,
+    F: FnMut(&Environment, Result<Number, Error>) -> bool,
+{
+    reader.set_ibase(env.ibase());
+    while let Some(statement) = reader.next() {
+        let result = statement.and_then(|expr| evaluate(env, expr));
+        if !report(env, result) {
+            break;
+        }
+        reader.set_ibase(env.ibase());
+    }
+}
+
+/// Loads a session saved by `save` into `env`. Source files are run
+/// statement by statement without printing their results, stopping at the
+/// first error.
+pub fn load<P: AsRef<Path>>(env: &mut Environment, path: P) -> Result<(), Error> {
+    let path = path.as_ref();
+    let read_error = |error: io::Error| ReadError(format!("{}: {}", path.display(), error));
+    if is_json(path) {
+        let json = fs::read_to_string(path).map_err(read_error)?;
+        let session: Session = serde_json::from_str(&json)
+            .map_err(|error| ReadError(format!("{}: {}", path.display(), error)))?;
+        return session.restore(env);
+    }
+    let reader = StatementReader::open(path).map_err(read_error)?;
+    load_source(env, reader)
+}
+
+// Runs a `.bc` session, stopping at the first error. Sessions are saved with
+// decimal literals, so they are read in base 10 whatever `ibase` is set to,
+// and `ibase` is left as it was.
+fn load_source<R: BufRead>(env: &mut Environment, reader: StatementReader<R>) -> Result<(), Error> {
+    let ibase = env.ibase();
+    env.set_ibase(10);
+    let mut outcome = Ok(());
+    run_source(env, reader, |_, result| match result {
+        Ok(_) => true,
+        Err(error) => {
+            outcome = Err(error);
+            false
+        }
+    });
+    env.set_ibase(ibase);
+    outcome
+}
+
+// Parses a saved value, which unlike a literal may be negative or, in IEEE
+// mode, infinite or NaN
+fn parse_value(value: &str) -> Option<Number> {
+    match value {
+        "inf" => Some(Number::infinity(false)),
+        "-inf" => Some(Number::infinity(true)),
+        "nan" => Some(Number::nan()),
+        _ if value.starts_with('-') => value[1..].parse::<Number>().ok().map(|n| n.neg()),
+        _ => value.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(env: &mut Environment, statement: &str) {
+        parse(statement).and_then(|expr| evaluate(env, expr)).unwrap();
+    }
+
+    #[test]
+    fn test_session_round_trips_through_json() {
+        let mut env = Environment::new();
+        run(&mut env, "let x = 0 - 2.50");
+        run(&mut env, "define sq(n) { return n * n }");
+        let json = serde_json::to_string(&Session::of(&env)).unwrap();
+        assert_eq!(
+            json,
+            r#"{"variables":{"x":"-2.50"},"functions":{"sq":{"params":["n"],"body":"{\n    return n * n\n}"}}}"#
+        );
+
+        let mut restored = Environment::new();
+        serde_json::from_str::<Session>(&json).unwrap().restore(&mut restored).unwrap();
+        assert_eq!(restored.get(String::from("x")), env.get(String::from("x")));
+        run(&mut restored, "y = sq(x)");
+        assert_eq!(restored.get(String::from("y")), Some(EnvValue::ComputedResult(
+            "6.2500".parse().unwrap(),
+        )));
+    }
+
+    #[test]
+    fn test_session_restores_non_finite_values() {
+        let mut env = Environment::new();
+        env.set_ieee(true);
+        run(&mut env, "let x = 0 - 1 / 0");
+        let session = Session::of(&env);
+        assert_eq!(session.variables.get("x").map(String::as_str), Some("-inf"));
+
+        let mut restored = Environment::new();
+        session.restore(&mut restored).unwrap();
+        assert_eq!(restored.get(String::from("x")), env.get(String::from("x")));
+    }
+
+    #[test]
+    fn test_source_sessions_only_keep_non_finite_values_in_ieee_mode() {
+        let mut env = Environment::new();
+        env.set_ieee(true);
+        run(&mut env, "let x = 0 - 1 / 0");
+        run(&mut env, "let y = 2");
+        assert_eq!(printer::session(&env), "x = -1 / 0\ny = 2\n");
+        env.set_ieee(false);
+        let source = printer::session(&env);
+        assert_eq!(source, "y = 2\n");
+        let mut restored = Environment::new();
+        load_source(&mut restored, StatementReader::new(source.as_bytes())).unwrap();
+        assert_eq!(restored.get(String::from("y")), env.get(String::from("y")));
+    }
+
+    #[test]
+    fn test_sessions_load_in_base_10_whatever_the_ibase() {
+        let mut env = Environment::new();
+        run(&mut env, "x = 10");
+        run(&mut env, "define f(n) { return n + 10 }");
+        let mut restored = Environment::new();
+        restored.set_ibase(16);
+        let source = printer::session(&env);
+        load_source(&mut restored, StatementReader::new(source.as_bytes())).unwrap();
+        assert_eq!(restored.ibase(), 16);
+        assert_eq!(restored.get(String::from("x")), env.get(String::from("x")));
+        restored.set_ibase(10);
+        run(&mut restored, "y = f(0)");
+        assert_eq!(restored.get(String::from("y")), Some(EnvValue::ComputedResult(
+            Number::from(10),
+        )));
+    }
+
+    #[test]
+    fn test_scripts_run_in_the_current_base() {
+        let mut env = Environment::new();
+        env.set_ibase(16);
+        let source = "x = 10\nibase = 2\ny = 10\n";
+        run_source(&mut env, StatementReader::new(source.as_bytes()), |_, result| {
+            result.is_ok()
+        });
+        assert_eq!(env.ibase(), 2);
+        assert_eq!(env.get(String::from("x")), Some(EnvValue::ComputedResult(Number::from(16))));
+        assert_eq!(env.get(String::from("y")), Some(EnvValue::ComputedResult(Number::from(2))));
+    }
+
+    #[test]
+    fn test_session_rejects_invalid_values() {
+        let session: Session = serde_json::from_str(r#"{"variables":{"x":"abc"}}"#).unwrap();
+        let error = session.restore(&mut Environment::new()).unwrap_err();
+        assert_eq!(error.to_string(), "Read Error: x isn't a number: abc");
+    }
+}
@@ -1,22 +1,115 @@
 use std::collections::HashMap;
+use std::error;
 use std::fmt;
+use std::mem;
+use std::rc::Rc;
 use std::result;
+use num_traits::ToPrimitive;
+use mathlib;
+use number::{Number, MAX_DIGITS};
 
-pub type Result = result::Result<f32, Error>;
+pub type Result = result::Result<Number, Error>;
+
+/// The number of decimal digits kept by division and other inexact
+/// operations unless configured otherwise.
+pub const DEFAULT_SCALE: u32 = 20;
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     UndefinedVariable(String),
     InvalidVariableReference(String),
     InvalidFunctionReference(String),
-    InvalidLambdaArgs(String, usize, usize),
-    InvalidNativeFunctionArgs(String, usize),
+    /// A function was called with the wrong number of arguments.
+    InvalidArgumentCount(String, Arity, usize),
     UndefinedFunction(String),
-    ParseError,
+    LoopControlOutsideLoop(String),
+    DivisionByZero,
+    NonIntegralOperand(String),
+    InvalidShift,
+    /// A function was called with an argument it isn't defined for.
+    DomainError(String, Number),
+    /// The result of an operator would have too many digits to compute.
+    Overflow(String),
+    /// An error that IEEE mode replaces with the given infinity or NaN. Native
+    /// functions return it to choose their result in IEEE mode, which is
+    /// otherwise NaN for domain errors and infinity for overflows.
+    WithIeeeValue(Box<Error>, Number),
+    /// A special variable such as `scale` was set to a value it can't take.
+    InvalidSetting(String, Number),
+    ParseError(SyntaxError),
+    /// The source of the statements couldn't be read.
+    ReadError(String),
+    /// An error raised by the expression at the given position.
+    Located(Span, Box<Error>),
+    /// An error raised while running the body of a function, along with the
+    /// position of the call.
+    InFunction(String, Span, Box<Error>),
+}
+
+impl Error {
+    /// Attaches the position of the expression that failed, unless the error
+    /// already points somewhere more precise.
+    pub fn at(self, span: Span) -> Error {
+        match self {
+            Located(..) | InFunction(..) | ParseError(_) => self,
+            error => Located(span, Box::new(error)),
+        }
+    }
+}
+
+/// A position in the source a statement was parsed from.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Span {
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column, counted in characters.
+    pub column: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Where the parser gave up on its input, and what it expected to find there.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SyntaxError {
+    /// 1-based line number within the parsed input.
+    pub line: usize,
+    /// 1-based column, counted in characters.
+    pub column: usize,
+    pub expected: String,
+    /// The text of the offending line, without its newline.
+    pub source_line: String,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Keep tabs in the padding so that the caret lines up however wide
+        // the terminal renders them
+        let padding: String = self.source_line
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        write!(
+            f,
+            "{}:{}: Parse Error: expected {}\n{}\n{}^",
+            self.line,
+            self.column,
+            self.expected,
+            self.source_line,
+            padding
+        )
+    }
 }
 
 use types::Error::*;
 
+impl error::Error for Error {}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -35,25 +128,42 @@ impl fmt::Display for Error {
                     fun_name
                 )
             }
-            InvalidLambdaArgs(ref fun_name, ref expected, ref actual) => {
+            InvalidArgumentCount(ref fun_name, ref expected, ref actual) => {
                 write!(
                     f,
-                    "Syntax Error: Function '{}' expects only {} arguments, but got {}",
+                    "Syntax Error: Function '{}' expects {}, but got {}",
                     fun_name,
                     expected,
                     actual
                 )
             }
-            InvalidNativeFunctionArgs(ref native_fn_name, ref actual) => {
-                write!(
-                    f,
-                    "Syntax Error: Native function '{}' can only be called with a single argument, but got {}",
-                    native_fn_name,
-                    actual
-                )
-            }
             UndefinedFunction(ref varname) => write!(f, "Undefined Function: {}", varname),
-            ParseError => write!(f, "Unable the parse the input. Please recheck."),
+            LoopControlOutsideLoop(ref keyword) => {
+                write!(f, "Syntax Error: '{}' used outside of a loop", keyword)
+            }
+            DivisionByZero => write!(f, "Runtime Error: Division by zero"),
+            NonIntegralOperand(ref op) => {
+                write!(f, "Runtime Error: Operator '{}' expects integral operands", op)
+            }
+            InvalidShift => {
+                write!(f, "Runtime Error: Shift amount must be a non-negative integer")
+            }
+            DomainError(ref fun_name, ref arg) => {
+                write!(f, "Runtime Error: {} is outside the domain of {}", arg, fun_name)
+            }
+            Overflow(ref op) => {
+                write!(f, "Runtime Error: Result of '{}' is too large to compute", op)
+            }
+            WithIeeeValue(ref error, _) => write!(f, "{}", error),
+            InvalidSetting(ref name, ref value) => {
+                write!(f, "Runtime Error: {} can't be set to {}", name, value)
+            }
+            ParseError(ref error) => write!(f, "{}", error),
+            ReadError(ref reason) => write!(f, "Read Error: {}", reason),
+            Located(ref span, ref error) => write!(f, "{}: {}", span, error),
+            InFunction(ref fun_name, ref span, ref error) => {
+                write!(f, "{}\n    in {}(), called at {}", error, fun_name, span)
+            }
         }
     }
 }
@@ -66,57 +176,350 @@ pub struct Lambda {
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct IfExpr {
-    pub condition: (Expr, Expr),
+    pub condition: Expr,
     pub body: Vec<Expr>,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+/// How many arguments a function takes.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Arity {
+    Exactly(usize),
+    /// Any number of arguments from the given minimum up.
+    AtLeast(usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Arity::Exactly(expected) => count == expected,
+            Arity::AtLeast(min) => count >= min,
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (prefix, count) = match *self {
+            Arity::Exactly(count) => ("", count),
+            Arity::AtLeast(count) => ("at least ", count),
+        };
+        let plural = if count == 1 { "" } else { "s" };
+        write!(f, "{}{} argument{}", prefix, count, plural)
+    }
+}
+
+type NativeBody = dyn Fn(&[Number], u32) -> Result;
+
+/// A function implemented in Rust. It's called with its evaluated arguments,
+/// whose number has already been checked against its arity, and the scale
+/// inexact results should be computed to.
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub arity: Arity,
+    body: Rc<NativeBody>,
+}
+
+impl NativeFunction {
+    pub fn new<F>(arity: Arity, body: F) -> NativeFunction
+    where
+        F: Fn(&[Number], u32) -> Result + 'static,
+    {
+        NativeFunction {
+            arity,
+            body: Rc::new(body),
+        }
+    }
+
+    pub fn call(&self, args: &[Number], scale: u32) -> Result {
+        (self.body)(args, scale)
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NativeFunction({:?})", self.arity)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum EnvValue {
-    ComputedResult(f32),
+    ComputedResult(Number),
     LambdaRef(Lambda),
-    NativeFn(fn(f32) -> f32),
+    NativeFn(NativeFunction),
+}
+
+/// Native functions are never equal, since closures can't be compared.
+impl PartialEq for EnvValue {
+    fn eq(&self, other: &EnvValue) -> bool {
+        match (self, other) {
+            (ComputedResult(a), ComputedResult(b)) => a == b,
+            (LambdaRef(a), LambdaRef(b)) => a == b,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum Expr {
-    ENum(f32),
+    ENum(Number),
     EVar(String),
+    ENeg(Box<Expr>),
     EAdd(Box<Expr>, Box<Expr>),
     ESub(Box<Expr>, Box<Expr>),
     EMul(Box<Expr>, Box<Expr>),
     EDiv(Box<Expr>, Box<Expr>),
     EExp(Box<Expr>, Box<Expr>),
+    EMod(Box<Expr>, Box<Expr>),
+    EIntDiv(Box<Expr>, Box<Expr>),
+    EBitAnd(Box<Expr>, Box<Expr>),
+    EBitOr(Box<Expr>, Box<Expr>),
+    EBitXor(Box<Expr>, Box<Expr>),
+    EShl(Box<Expr>, Box<Expr>),
+    EShr(Box<Expr>, Box<Expr>),
+    EEq(Box<Expr>, Box<Expr>),
+    ENe(Box<Expr>, Box<Expr>),
+    ELt(Box<Expr>, Box<Expr>),
+    ELe(Box<Expr>, Box<Expr>),
+    EGt(Box<Expr>, Box<Expr>),
+    EGe(Box<Expr>, Box<Expr>),
+    EAnd(Box<Expr>, Box<Expr>),
+    EOr(Box<Expr>, Box<Expr>),
+    ENot(Box<Expr>),
     ELet(String, Box<Expr>),
+    EAssign(String, Box<Expr>),
+    EPreInc(String),
+    EPreDec(String),
+    EPostInc(String),
+    EPostDec(String),
     EIf(Vec<IfExpr>, Vec<Expr>),
+    EWhile(Box<Expr>, Vec<Expr>),
+    EFor(Option<Box<Expr>>, Option<Box<Expr>>, Option<Box<Expr>>, Vec<Expr>),
+    EBreak,
+    EContinue,
     EFunCall(String, Vec<Expr>),
     EDefun(String, Lambda),
     EReturn(Box<Expr>),
+    ELocated(Span, Box<Expr>),
 }
 
+/// Variables and functions visible to the evaluator.
+///
+/// Top-level definitions live in the global scope. A function call gets a
+/// fresh stack of local scopes that starts with its parameters and cannot see
+/// the locals of its caller, and every `if` branch pushes one more local scope
+/// for the duration of the branch. `let` always declares a name in the
+/// innermost scope, while assignment (`x = ...`, `x += ...`, `x++`, ...)
+/// updates the innermost scope that already defines the name, falling back to
+/// the globals.
 #[derive(Clone)]
-pub struct Environment(HashMap<String, EnvValue>);
+pub struct Environment {
+    globals: HashMap<String, EnvValue>,
+    locals: Vec<HashMap<String, EnvValue>>,
+    scale: u32,
+    ieee: bool,
+    bc_mathlib: bool,
+    ibase: u32,
+    obase: u32,
+}
 
 use self::EnvValue::*;
 
 impl Environment {
     pub fn new() -> Environment {
-        let mut env = Environment(HashMap::new());
-        let fun_name = String::from("sqrt");
-        env.add(fun_name.clone(), NativeFn(|x| x.sqrt()));
+        let mut env = Environment {
+            globals: HashMap::new(),
+            locals: Vec::new(),
+            scale: DEFAULT_SCALE,
+            ieee: false,
+            bc_mathlib: false,
+            ibase: 10,
+            obase: 10,
+        };
+        mathlib::define(&mut env);
         env
     }
+    /// Defines the global native function `name`, replacing whatever it was
+    /// bound to.
+    pub fn define_native<F>(&mut self, name: &str, arity: Arity, body: F) -> &mut Environment
+    where
+        F: Fn(&[Number], u32) -> Result + 'static,
+    {
+        self.globals.insert(name.to_string(), NativeFn(NativeFunction::new(arity, body)));
+        self
+    }
     pub fn get(&self, var_name: String) -> Option<EnvValue> {
-        self.0.get(&var_name).map(|e| e.clone())
+        self.locals
+            .iter()
+            .rev()
+            .chain(Some(&self.globals))
+            .filter_map(|scope| scope.get(&var_name))
+            .next()
+            .cloned()
     }
+    /// Declares `var_name` in the innermost scope.
     pub fn add(&mut self, var_name: String, result: EnvValue) -> &mut Environment {
-        &self.0.insert(var_name, result);
+        match self.locals.last_mut() {
+            Some(scope) => scope.insert(var_name, result),
+            None => self.globals.insert(var_name, result),
+        };
+        self
+    }
+    /// Updates `var_name` in the innermost scope that already defines it, or
+    /// declares it in the innermost scope if it isn't defined anywhere.
+    pub fn assign(&mut self, var_name: String, result: EnvValue) -> &mut Environment {
+        let defined_in = self.locals
+            .iter_mut()
+            .rev()
+            .chain(Some(&mut self.globals))
+            .find(|scope| scope.contains_key(&var_name));
+        match defined_in {
+            Some(scope) => {
+                scope.insert(var_name, result);
+            }
+            None => {
+                self.add(var_name, result);
+            }
+        }
+        self
+    }
+    /// The names of all global variables and functions, in alphabetical order.
+    pub fn names(&self) -> Vec<String> {
+        self.globals().into_iter().map(|(name, _)| name.to_string()).collect()
+    }
+    /// The global variables and functions, in alphabetical order of name.
+    pub fn globals(&self) -> Vec<(&str, &EnvValue)> {
+        let mut globals: Vec<(&str, &EnvValue)> = self.globals
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+            .collect();
+        globals.sort_by_key(|&(name, _)| name);
+        globals
+    }
+    /// Removes the global variable or function `var_name`, returning what it
+    /// was bound to.
+    pub fn remove(&mut self, var_name: &str) -> Option<EnvValue> {
+        self.globals.remove(var_name)
+    }
+    pub fn push_scope(&mut self) {
+        self.locals.push(HashMap::new());
+    }
+    pub fn pop_scope(&mut self) {
+        self.locals.pop();
+    }
+    /// Starts a function call with a single, empty local scope, returning the
+    /// caller's scopes so that they can be restored by `leave_function`.
+    pub fn enter_function(&mut self) -> Vec<HashMap<String, EnvValue>> {
+        mem::replace(&mut self.locals, vec![HashMap::new()])
+    }
+    pub fn leave_function(&mut self, caller_scopes: Vec<HashMap<String, EnvValue>>) {
+        self.locals = caller_scopes;
+    }
+    /// The number of decimal digits kept by division, exponentiation and
+    /// native functions such as `sqrt`.
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+    pub fn set_scale(&mut self, scale: u32) -> &mut Environment {
+        self.scale = scale;
+        self
+    }
+    /// Whether division by zero, domain errors and overflow produce
+    /// infinities and NaN, as in IEEE 754 floating point, instead of errors.
+    pub fn ieee(&self) -> bool {
+        self.ieee
+    }
+    pub fn set_ieee(&mut self, ieee: bool) -> &mut Environment {
+        self.ieee = ieee;
         self
     }
+    /// Whether GNU bc's math library is emulated, as with `bc -l`: the
+    /// functions `s`, `c`, `a`, `l`, `e` and `j` are defined, `scale` is a
+    /// special variable that reads and sets the scale, and assignments and
+    /// definitions print nothing.
+    pub fn bc_mathlib(&self) -> bool {
+        self.bc_mathlib
+    }
+    pub fn set_bc_mathlib(&mut self, bc_mathlib: bool) -> &mut Environment {
+        if bc_mathlib && !self.bc_mathlib {
+            mathlib::define_bc(self);
+        }
+        self.bc_mathlib = bc_mathlib;
+        self
+    }
+    /// The base that literals without a prefix are read in, from 2 to 36.
+    pub fn ibase(&self) -> u32 {
+        self.ibase
+    }
+    pub fn set_ibase(&mut self, ibase: u32) -> &mut Environment {
+        self.ibase = ibase;
+        self
+    }
+    /// The base that results are printed in, from 2 to 36.
+    pub fn obase(&self) -> u32 {
+        self.obase
+    }
+    pub fn set_obase(&mut self, obase: u32) -> &mut Environment {
+        self.obase = obase;
+        self
+    }
+    /// The value of `var_name`, if it's a special variable that stands for a
+    /// setting of the environment rather than for a value of its own.
+    pub fn special(&self, var_name: &str) -> Option<Number> {
+        match var_name {
+            "scale" if self.bc_mathlib => Some(Number::from(i64::from(self.scale))),
+            "ibase" => Some(Number::from(i64::from(self.ibase))),
+            "obase" => Some(Number::from(i64::from(self.obase))),
+            _ => None,
+        }
+    }
+    /// Changes the setting that the special variable `var_name` stands for,
+    /// or returns `None` if `var_name` isn't special. Fractional values are
+    /// truncated, as in bc, and `scale` can't be above `MAX_DIGITS`.
+    pub fn set_special(
+        &mut self,
+        var_name: &str,
+        value: &Number,
+    ) -> Option<result::Result<(), Error>> {
+        match var_name {
+            "scale" if self.bc_mathlib => Some(match value.trunc().to_u32() {
+                Some(scale) if !value.is_negative() && scale <= MAX_DIGITS => {
+                    self.scale = scale;
+                    Ok(())
+                }
+                _ => Err(InvalidSetting(var_name.to_string(), value.clone())),
+            }),
+            "ibase" | "obase" => Some(match value.trunc().to_u32() {
+                Some(base @ 2..=36) => {
+                    if var_name == "ibase" {
+                        self.ibase = base;
+                    } else {
+                        self.obase = base;
+                    }
+                    Ok(())
+                }
+                _ => Err(InvalidSetting(var_name.to_string(), value.clone())),
+            }),
+            _ => None,
+        }
+    }
 }
 
-pub fn display(r: Result) -> String {
+/// Prints a result, with numbers in base `obase`.
+pub fn display(r: Result, obase: u32) -> String {
     match r {
-        Ok(value) => value.to_string(),
+        Ok(value) => value.to_string_radix(obase),
         Err(error) => error.to_string(),
     }
 }
+
+/// Like `display`, but prefixes errors that carry a position with the name of
+/// the file they come from, as in `script.bc:42:7: Undefined Variable: psi`.
+pub fn display_in(file_name: &str, r: Result, obase: u32) -> String {
+    match r {
+        Err(error @ Located(..)) |
+        Err(error @ InFunction(..)) |
+        Err(error @ ParseError(_)) => format!("{}:{}", file_name, error),
+        r => display(r, obase),
+    }
+}